@@ -0,0 +1,294 @@
+use crate::deframer::NrfFrame;
+use crate::eof::EndWithInputs;
+use crate::generate::GenerateOpt;
+use crate::{process_channels, range_validator, Channel, DataRate, Opt};
+use rustradio::blocks::VectorSource;
+use rustradio::graph::GraphRunner;
+use rustradio::mtgraph::MTGraph;
+use rustradio::Error;
+use std::time::UNIX_EPOCH;
+use structopt::StructOpt;
+
+// default address of nRF24 transceivers, truncated to the address length
+const ADDRESS: [u8; 5] = [0xe7; 5];
+
+/// Options of the benchmark subcommand
+#[derive(StructOpt, Debug)]
+pub struct BenchmarkOpt {
+    /// Channel the packets are sent on, must be within range [0,125]
+    #[structopt(short = "c", long = "channel", default_value = "40", validator = range_validator(0, 125))]
+    channel: u8,
+
+    /// Data rate (250kbps, 1Mpbs or 2Mpbs)
+    #[structopt(short="r", long = "rate", default_value = "1", possible_values = &["250k", "1", "2"])]
+    rate: DataRate,
+
+    /// Address length in bytes
+    #[structopt(short = "a", long = "alen", default_value = "5", possible_values = &["3", "4", "5"])]
+    address_length: usize,
+
+    /// Payload length in bytes, must be within range [0,32]
+    #[structopt(short = "l", long = "plen", default_value = "16", validator = range_validator(0, 32))]
+    payload_length: usize,
+
+    /// Sends Enhanced ShockBurst (ESB) packets, decoded without knowing their payload length
+    #[structopt(short = "e", long = "shockburst")]
+    shockburst: bool,
+
+    /// CRC length in bytes
+    #[structopt(long = "crc", default_value = "2", possible_values = &["0", "1", "2"])]
+    crc_length: usize,
+
+    /// Decodes packets of the known address only, rather than of any address
+    #[structopt(long = "known-address")]
+    known_address: bool,
+
+    /// Corrects single bit errors with a 2-byte CRC, implies --known-address
+    #[structopt(long = "correct")]
+    correct: bool,
+
+    /// Corrects bit errors with a 2-byte CRC by flipping the least confident bits, implies --known-address
+    #[structopt(long = "soft")]
+    soft: bool,
+
+    /// Number of packets sent per configuration
+    #[structopt(short = "n", long = "count", default_value = "100")]
+    count: usize,
+
+    /// Signal-to-noise ratios in dB, measured within the bandwidth of the data rate
+    #[structopt(
+        long = "snr",
+        default_value = "6,9,12,15,18,21,24,27,30",
+        require_delimiter = true,
+        allow_hyphen_values = true
+    )]
+    snrs: Vec<f64>,
+
+    /// Carrier frequency offsets in Hz
+    #[structopt(
+        long = "freq-offset",
+        default_value = "0",
+        require_delimiter = true,
+        allow_hyphen_values = true
+    )]
+    freq_offsets: Vec<f64>,
+
+    /// Sample rates in MHz
+    #[structopt(
+        short = "s",
+        long = "sample",
+        default_value = "4",
+        require_delimiter = true
+    )]
+    sample_rates_mhz: Vec<f32>,
+
+    /// Symbol rate error in parts per million
+    #[structopt(long = "drift", default_value = "0", allow_hyphen_values = true)]
+    drift_ppm: f64,
+
+    /// Seed of the noise generator
+    #[structopt(long = "seed", default_value = "1")]
+    seed: u64,
+}
+
+/// Packets received in a single configuration of the benchmark
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Outcome {
+    pub sent: usize,
+    // sent packets received correctly
+    pub received: usize,
+    // received packets not matching any sent packet
+    pub false_positives: usize,
+    // duration of the signal in seconds
+    pub seconds: f64,
+}
+
+impl Outcome {
+    // packet error rate
+    pub fn per(&self) -> f64 {
+        1.0 - self.received as f64 / self.sent as f64
+    }
+}
+
+impl BenchmarkOpt {
+    // packets of the given configuration, spaced by twice their duration
+    fn generate_opt(&self, sample_rate_mhz: f32, freq_offset: f64, snr: f64) -> GenerateOpt {
+        let bits = 8 * (1 + self.address_length + self.payload_length + self.crc_length)
+            + if self.shockburst { 9 } else { 0 };
+        let interval_ms = 2.0 * bits as f64 / self.rate.bits_per_second() as f64 * 1000.0;
+        GenerateOpt {
+            output: String::new(),
+            channels: vec![self.channel],
+            sample_rate_mhz,
+            center_freq_mhz: None,
+            rate: self.rate,
+            address: hex::encode(&ADDRESS[..self.address_length]),
+            // distinct payload for every packet
+            payloads: (0..self.count)
+                .map(|i| {
+                    let payload: Vec<u8> = (0..self.payload_length)
+                        .map(|j| (i * 31 + j * 17) as u8)
+                        .collect();
+                    hex::encode(payload)
+                })
+                .collect(),
+            shockburst: self.shockburst,
+            crc_length: self.crc_length,
+            count: self.count,
+            interval_ms,
+            snr: Some(snr),
+            freq_offset,
+            drift_ppm: self.drift_ppm,
+            multipath_delay_ns: None,
+            multipath_gain: 0.0,
+            seed: self.seed,
+        }
+    }
+
+    // decodes the given framing irrespective of the address, to count false positives as well,
+    // unless the address is known or required to correct bit errors or without CRC
+    fn decode_opt(&self, generate: &GenerateOpt) -> Opt {
+        let known_address = self.known_address || self.correct || self.soft || self.crc_length == 0;
+        let mut opt = Opt::defaults();
+        opt.channels = vec![self.channel];
+        opt.sample_rate_mhz = Some(generate.sample_rate_mhz);
+        opt.center_freq_mhz = Some((generate.center_freq() / 1_000_000.0) as f32);
+        opt.address_length = self.address_length;
+        opt.address_prefix = known_address
+            .then(|| generate.address.clone())
+            .into_iter()
+            .collect();
+        opt.payload_length = (!self.shockburst).then_some(self.payload_length);
+        opt.shockburst = self.shockburst;
+        opt.crc_length = self.crc_length;
+        opt.correct = self.correct;
+        opt.soft = self.soft;
+        opt.rate = self.rate;
+        opt
+    }
+
+    // sends packets through the demodulation chain used for receiving
+    fn run_once(&self, sample_rate_mhz: f32, freq_offset: f64, snr: f64) -> Result<Outcome, Error> {
+        let generate = self.generate_opt(sample_rate_mhz, freq_offset, snr);
+        let (samples, sent) = generate.signal()?;
+        let seconds = samples.len() as f64 / generate.sample_rate();
+        let opt = self.decode_opt(&generate);
+
+        let mut graph = MTGraph::new();
+        let source = VectorSource::new(samples);
+        let input = source.out();
+        graph.add(Box::new(EndWithInputs::new(source, (), input.clone())));
+        let channel = Channel::Nrf(self.channel);
+        let outputs = process_channels(
+            &mut graph,
+            &opt,
+            input,
+            opt.modulation(channel),
+            &[channel],
+            UNIX_EPOCH,
+        )?;
+        graph.run()?;
+
+        let mut received = Vec::new();
+        for output in outputs {
+            while let Some((frame, _)) = output.pop() {
+                received.push(frame);
+            }
+        }
+        let tolerance = (generate.interval_ms / 1000.0 * generate.sample_rate() / 2.0) as u64;
+        Ok(evaluate(&sent, &received, tolerance, seconds))
+    }
+}
+
+// matches received packets with sent packets starting at about the same position
+fn evaluate(sent: &[NrfFrame], received: &[NrfFrame], tolerance: u64, seconds: f64) -> Outcome {
+    let mut matched = vec![false; sent.len()];
+    let mut false_positives = 0;
+    for frame in received {
+        let found = sent.iter().position(|s| {
+            s.sample_start.abs_diff(frame.sample_start) <= tolerance
+                && s.address == frame.address
+                && s.payload == frame.payload
+                && s.pcf == frame.pcf
+        });
+        match found {
+            Some(i) if !matched[i] => matched[i] = true,
+            _ => false_positives += 1,
+        }
+    }
+    Outcome {
+        sent: sent.len(),
+        received: matched.iter().filter(|m| **m).count(),
+        false_positives,
+        seconds,
+    }
+}
+
+/// Measures packet error rate and false positives for all combinations of sample rate, frequency
+/// offset and signal-to-noise ratio
+pub fn run(opt: &BenchmarkOpt) -> Result<(), Error> {
+    println!("Sample Rate (MHz) | Offset (kHz) | SNR (dB) | Sent | Received | PER    | False Positives (1/s)");
+    for sample_rate_mhz in &opt.sample_rates_mhz {
+        for freq_offset in &opt.freq_offsets {
+            for snr in &opt.snrs {
+                let outcome = opt.run_once(*sample_rate_mhz, *freq_offset, *snr)?;
+                println!(
+                    "{:>17} | {:>12.1} | {:>8.1} | {:>4} | {:>8} | {:>6.3} | {:>21.1}",
+                    sample_rate_mhz,
+                    freq_offset / 1000.0,
+                    snr,
+                    outcome.sent,
+                    outcome.received,
+                    outcome.per(),
+                    outcome.false_positives as f64 / outcome.seconds
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_benchmark() {
+    // all packets are received at a high signal-to-noise ratio, also with frequency offset
+    for (args, freq_offset) in [(&["-l", "8"][..], 20_000.0), (&["-e"][..], 50_000.0)] {
+        let opt = BenchmarkOpt::from_iter(["benchmark", "-n", "20"].iter().chain(args));
+        let outcome = opt
+            .run_once(4.0, freq_offset, 40.0)
+            .expect("Benchmark failed");
+        assert_eq!(outcome.sent, 20);
+        assert_eq!(outcome.received, outcome.sent, "{:?} {:?}", args, outcome);
+        assert_eq!(outcome.false_positives, 0);
+    }
+
+    let sent = [NrfFrame {
+        address: vec![1, 2, 3],
+        payload: vec![4],
+        sample_start: 1000,
+        ..Default::default()
+    }];
+    let received = [
+        NrfFrame {
+            sample_start: 1010,
+            ..sent[0].clone()
+        },
+        // received twice
+        NrfFrame {
+            sample_start: 1020,
+            ..sent[0].clone()
+        },
+        NrfFrame {
+            payload: vec![5],
+            ..sent[0].clone()
+        },
+    ];
+    assert_eq!(
+        evaluate(&sent, &received, 50, 1.0),
+        Outcome {
+            sent: 1,
+            received: 1,
+            false_positives: 2,
+            seconds: 1.0
+        }
+    );
+}
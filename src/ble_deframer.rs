@@ -0,0 +1,314 @@
+use crate::deframer::{NrfFrame, StreamTiming};
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp, Streamp};
+use rustradio::Error;
+
+const CRC24: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_24_BLE);
+
+// access address used on all advertising channels
+const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8e89bed6;
+
+// preamble followed by access address, both transmitted LSB first
+// the preamble alternates and ends with the inverse of the first access address bit
+const SYNC_WORD: u64 = 0xaa | (ADVERTISING_ACCESS_ADDRESS as u64) << 8;
+const SYNC_WORD_BITS: usize = 40;
+
+// maximum payload length of legacy advertising PDUs
+const MAX_PAYLOAD_LEN: u8 = 37;
+
+/// Bits of the longest advertising frame, including sync word, PDU header and CRC
+pub const MAX_FRAME_BITS: usize = SYNC_WORD_BITS + (2 + MAX_PAYLOAD_LEN as usize + 3) * 8;
+
+// RF channel (2400 MHz + n MHz) of a BLE channel index
+pub fn rf_channel(index: u8) -> u8 {
+    match index {
+        37 => 2,
+        38 => 26,
+        39 => 80,
+        0..=10 => 4 + 2 * index,
+        _ => 6 + 2 * index,
+    }
+}
+
+// initial state of the whitening LFSR, bit 7 holds position 6 of the LFSR defined by the
+// specification, position 0 is set and positions 1 to 6 hold the channel index
+fn whitening_init(index: u8) -> u8 {
+    index.reverse_bits() | 0x02
+}
+
+// advances the whitening LFSR (x^7 + x^4 + 1) and returns the next whitening bit
+fn whitening_bit(lfsr: &mut u8) -> bool {
+    let bit = *lfsr & 0x80 != 0;
+    if bit {
+        *lfsr ^= 0x11;
+    }
+    *lfsr <<= 1;
+    bit
+}
+
+/// Header of a BLE advertising channel PDU
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BleHeader {
+    // BLE channel index the PDU was received on
+    pub channel_index: u8,
+    pub pdu_type: u8,
+    // channel selection algorithm #2 supported
+    pub ch_sel: bool,
+    // random (true) or public (false) transmitter address
+    pub tx_add: bool,
+    // random (true) or public (false) receiver address
+    pub rx_add: bool,
+}
+
+impl BleHeader {
+    fn from_bytes(channel_index: u8, header: u8) -> Self {
+        BleHeader {
+            channel_index,
+            pdu_type: header & 0x0f,
+            ch_sel: header & 0x20 != 0,
+            tx_add: header & 0x40 != 0,
+            rx_add: header & 0x80 != 0,
+        }
+    }
+
+    // first byte of the PDU header as transmitted
+    pub fn to_byte(self) -> u8 {
+        self.pdu_type
+            | (self.ch_sel as u8) << 5
+            | (self.tx_add as u8) << 6
+            | (self.rx_add as u8) << 7
+    }
+
+    pub fn pdu_type_name(&self) -> &'static str {
+        match self.pdu_type {
+            0 => "ADV_IND",
+            1 => "ADV_DIRECT_IND",
+            2 => "ADV_NONCONN_IND",
+            3 => "SCAN_REQ",
+            4 => "SCAN_RSP",
+            5 => "CONNECT_IND",
+            6 => "ADV_SCAN_IND",
+            7 => "ADV_EXT_IND",
+            _ => "RESERVED",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum BleDecoder {
+    // searching for preamble and access address within the most recent bits
+    Sync(u64),
+    // receiving dewhitened PDU and CRC, the partially received byte and its number of bits
+    RecvPdu {
+        data: Vec<u8>,
+        byte: u8,
+        bits: u8,
+        lfsr: u8,
+    },
+}
+
+impl BleDecoder {
+    // pushes a single bit received at position pos within the demodulated stream
+    fn push_bit(
+        self,
+        index: u8,
+        timing: &StreamTiming,
+        out: &NoCopyStreamp<NrfFrame>,
+        bit: bool,
+        pos: u64,
+    ) -> BleDecoder {
+        match self {
+            BleDecoder::Sync(window) => {
+                let window = window >> 1 | (bit as u64) << (SYNC_WORD_BITS - 1);
+                if window == SYNC_WORD {
+                    BleDecoder::RecvPdu {
+                        data: Vec::with_capacity(2 + MAX_PAYLOAD_LEN as usize + 3),
+                        byte: 0,
+                        bits: 0,
+                        lfsr: whitening_init(index),
+                    }
+                } else {
+                    BleDecoder::Sync(window)
+                }
+            }
+
+            BleDecoder::RecvPdu {
+                mut data,
+                mut byte,
+                mut bits,
+                mut lfsr,
+            } => {
+                byte |= ((bit ^ whitening_bit(&mut lfsr)) as u8) << bits;
+                bits += 1;
+                if bits < 8 {
+                    return BleDecoder::RecvPdu {
+                        data,
+                        byte,
+                        bits,
+                        lfsr,
+                    };
+                }
+                data.push(byte);
+                match data.len() {
+                    // header received, second byte holds the payload length
+                    2 if data[1] > MAX_PAYLOAD_LEN => BleDecoder::Sync(0),
+                    // header, payload and CRC received
+                    n if n >= 2 && n == 2 + data[1] as usize + 3 => {
+                        let crc = u32::from_le_bytes([data[n - 3], data[n - 2], data[n - 1], 0]);
+                        if CRC24.checksum(&data[..n - 3]) == crc {
+                            // frame length in bits including preamble and access address
+                            let frame_len = (SYNC_WORD_BITS + n * 8) as u64;
+                            let start = timing.preceding(pos, frame_len - 1);
+                            let sample_start = timing.sample_index(start);
+                            let sample_count = frame_len as f64 * timing.samples_per_symbol;
+                            out.push(
+                                NrfFrame {
+                                    channel: rf_channel(index),
+                                    address: ADVERTISING_ACCESS_ADDRESS.to_be_bytes().to_vec(),
+                                    payload: data[2..n - 3].to_vec(),
+                                    crc: Some(crc),
+                                    sample_start,
+                                    sample_count: sample_count as u64,
+                                    timestamp: timing.timestamp(sample_start),
+                                    ble: Some(BleHeader::from_bytes(index, data[0])),
+                                    ..Default::default()
+                                },
+                                &[],
+                            );
+                        }
+                        BleDecoder::Sync(0)
+                    }
+                    _ => BleDecoder::RecvPdu {
+                        data,
+                        byte: 0,
+                        bits: 0,
+                        lfsr,
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Deframer of BLE advertising channel PDUs
+pub struct BleDeframer {
+    src: Streamp<u8>,
+    // positions of the bits within the demodulated stream
+    positions: Streamp<u64>,
+    dst: NoCopyStreamp<NrfFrame>,
+    // BLE channel index, used for dewhitening
+    index: u8,
+    timing: StreamTiming,
+    state: BleDecoder,
+}
+
+impl BleDeframer {
+    pub fn new(src: Streamp<u8>, positions: Streamp<u64>, index: u8, timing: StreamTiming) -> Self {
+        Self {
+            src,
+            positions,
+            dst: NoCopyStream::newp(),
+            index,
+            timing,
+            state: BleDecoder::Sync(0),
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+}
+
+impl Block for BleDeframer {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ti = self.src.clone();
+        let (input, _tags) = ti.read_buf()?;
+        let tp = self.positions.clone();
+        let (positions, _tags) = tp.read_buf()?;
+        let n = std::cmp::min(input.len(), positions.len());
+        if n == 0 {
+            drop((input, positions));
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+
+        self.state = input
+            .iter()
+            .copied()
+            .map(|b| b != 0)
+            .zip(positions.iter().copied())
+            .take(n)
+            .fold(self.state.clone(), |s, (b, pos)| {
+                s.push_bit(self.index, &self.timing, &self.dst, b, pos)
+            });
+
+        input.consume(n);
+        positions.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockName for BleDeframer {
+    fn block_name(&self) -> &str {
+        "BLE deframer"
+    }
+}
+
+impl BlockEOF for BleDeframer {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&(self.src.clone(), self.positions.clone()), &self.dst)
+    }
+}
+
+#[test]
+fn test_ble() {
+    let index = 38;
+    // ADV_NONCONN_IND with random address and flags
+    let mut pdu = vec![
+        0x42, 9, 0x66, 0x55, 0x44, 0x33, 0x22, 0xc1, 0x02, 0x01, 0x06,
+    ];
+    pdu.extend(&CRC24.checksum(&pdu).to_le_bytes()[..3]);
+
+    // LSB first, PDU and CRC whitened
+    let mut lfsr = whitening_init(index);
+    let bits: Vec<bool> = (0..SYNC_WORD_BITS)
+        .map(|i| SYNC_WORD >> i & 1 == 1)
+        .chain(
+            pdu.iter()
+                .flat_map(|b| (0..8).map(move |i| b >> i & 1 == 1))
+                .map(|b| b ^ whitening_bit(&mut lfsr)),
+        )
+        .collect();
+
+    let out = NoCopyStream::newp();
+    let mut state = BleDecoder::Sync(0);
+    // prepend noise to shift packet within stream
+    for (pos, bit) in [true, false, false].into_iter().chain(bits).enumerate() {
+        state = state.push_bit(index, &StreamTiming::default(), &out, bit, pos as u64);
+    }
+    let (out, _) = out.pop().expect("Parsing failed");
+    assert_eq!(out.channel, 26);
+    assert_eq!(out.address, vec![0x8e, 0x89, 0xbe, 0xd6]);
+    assert_eq!(out.payload, pdu[2..11]);
+    assert_eq!(out.sample_start, 3);
+    let header = out.ble.expect("Header missing");
+    assert_eq!(header.pdu_type_name(), "ADV_NONCONN_IND");
+    assert!(header.tx_add);
+    assert!(!header.rx_add);
+    assert_eq!(header.to_byte(), pdu[0]);
+}
+
+#[test]
+fn test_whitening() {
+    // first whitening bits on channel 37 (0b100101), starting with the channel index LSB
+    let mut lfsr = whitening_init(37);
+    let bits: Vec<bool> = (0..7).map(|_| whitening_bit(&mut lfsr)).collect();
+    assert_eq!(bits, vec![true, false, true, true, false, false, false]);
+    assert_eq!(rf_channel(0), 4);
+    assert_eq!(rf_channel(11), 28);
+    assert_eq!(rf_channel(36), 78);
+}
@@ -0,0 +1,220 @@
+use crate::eof;
+use rustfft::{Fft, FftPlanner};
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp};
+use rustradio::{Complex, Error, Float};
+use std::sync::Arc;
+
+// upper limit of the number of bins, i.e. the size of the FFT
+const MAX_BINS: u64 = 1024;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Returns the number of bins needed to place all channels at the center of a bin, and the bin of
+/// each channel, given the sample rate and the offsets of the channels from the center frequency
+/// in the same unit
+pub fn bins(sample_rate: u64, offsets: &[i64]) -> Option<(usize, Vec<usize>)> {
+    let width = offsets
+        .iter()
+        .fold(sample_rate, |w, o| gcd(w, o.unsigned_abs()));
+    let bins = sample_rate / width;
+    if bins > MAX_BINS {
+        return None;
+    }
+    let channels = offsets
+        .iter()
+        .map(|o| o.div_euclid(width as i64).rem_euclid(bins as i64) as usize)
+        .collect();
+    Some((bins as usize, channels))
+}
+
+/// Polyphase filter bank splitting a wideband stream into decimated channels
+///
+/// The input is split into bins equally spaced across the sample rate. Every bin is mixed down to
+/// baseband, filtered with the same low pass filter and decimated, equivalent to a frequency shift
+/// followed by a FIR filter and a decimation per bin. The decimation may be smaller than the
+/// number of bins, so that the filter can be wider than a bin. Only the selected bins are output.
+pub struct Channelizer {
+    src: Streamp<Complex>,
+    // selected bins and their output streams
+    dsts: Vec<(usize, Streamp<Complex>)>,
+    // time-reversed filter taps, zero padded to a multiple of the number of bins
+    taps: Vec<Float>,
+    bins: usize,
+    decimation: usize,
+    fft: Arc<dyn Fft<f32>>,
+    scratch: Vec<Complex>,
+    // most recent input samples, the first one at position history_start
+    history: Vec<Complex>,
+    history_start: i64,
+    // position of the newest input sample of the next output sample
+    next: i64,
+}
+
+impl Channelizer {
+    pub fn new(
+        src: Streamp<Complex>,
+        taps: &[Float],
+        bins: usize,
+        decimation: usize,
+        channels: &[usize],
+    ) -> Self {
+        assert!(bins > 0 && decimation > 0);
+        let len = taps.len().div_ceil(bins) * bins;
+        let mut taps = taps.to_vec();
+        taps.resize(len, 0.0);
+        taps.reverse();
+
+        let fft = FftPlanner::new().plan_fft_inverse(bins);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        Self {
+            src,
+            dsts: channels.iter().map(|c| (*c, Stream::newp())).collect(),
+            taps,
+            bins,
+            decimation,
+            fft,
+            scratch,
+            // samples preceding the stream are zero
+            history: vec![Complex::default(); len - 1],
+            history_start: 1 - len as i64,
+            next: 0,
+        }
+    }
+
+    /// Get output streams of the selected bins, in the order of selection.
+    pub fn out(&self) -> Vec<Streamp<Complex>> {
+        self.dsts.iter().map(|(_, dst)| dst.clone()).collect()
+    }
+
+    // folds the filtered window ending at the next output position into the bins, rotated to
+    // continue the phase of the mixers, ready for the inverse FFT
+    fn fold(&self, out: &mut [Complex]) {
+        let start = (self.next + 1 - self.taps.len() as i64 - self.history_start) as usize;
+        let window = &self.history[start..start + self.taps.len()];
+        for (w, t) in window
+            .chunks_exact(self.bins)
+            .zip(self.taps.chunks_exact(self.bins))
+        {
+            for ((o, w), t) in out.iter_mut().zip(w).zip(t) {
+                *o += w * t;
+            }
+        }
+        // polyphase branches are in reverse order due to the reversed taps
+        out.reverse();
+        out.rotate_left(self.next as usize % self.bins);
+    }
+}
+
+impl Block for Channelizer {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, _tags) = self.src.read_buf()?;
+        if input.is_empty() {
+            drop(input);
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        let mut outs = self
+            .dsts
+            .iter()
+            .map(|(_, dst)| dst.write_buf())
+            .collect::<Result<Vec<_>, _>>()?;
+        let space = outs.iter().map(|o| o.len()).min().unwrap_or(0);
+        if space == 0 {
+            return Ok(BlockRet::OutputFull);
+        }
+
+        let n = std::cmp::min(input.len(), space * self.decimation);
+        self.history.extend(input.iter().take(n));
+        input.consume(n);
+
+        let end = self.history_start + self.history.len() as i64;
+        let count = std::cmp::min(
+            space,
+            ((end - self.next).max(0) as usize).div_ceil(self.decimation),
+        );
+        let mut spectra = vec![Complex::default(); count * self.bins];
+        for spectrum in spectra.chunks_exact_mut(self.bins) {
+            self.fold(spectrum);
+            self.next += self.decimation as i64;
+        }
+        if count > 0 {
+            self.fft
+                .process_with_scratch(&mut spectra, &mut self.scratch);
+        }
+
+        for ((bin, _), o) in self.dsts.iter().zip(outs.iter_mut()) {
+            for (w, spectrum) in o.slice().iter_mut().zip(spectra.chunks_exact(self.bins)) {
+                *w = spectrum[*bin];
+            }
+        }
+        outs.into_iter().for_each(|o| o.produce(count, &[]));
+
+        // drop samples no longer covered by the filter
+        let drop = (self.next + 1 - self.taps.len() as i64 - self.history_start) as usize;
+        self.history.drain(..drop);
+        self.history_start += drop as i64;
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for Channelizer {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.out())
+    }
+}
+
+impl BlockName for Channelizer {
+    fn block_name(&self) -> &str {
+        "Channelizer"
+    }
+}
+
+#[test]
+fn test_channelizer() {
+    use crate::test_util::stream_of;
+
+    let (bins, decimation) = (4, 3);
+    let taps: Vec<Float> = (0..6).map(|i| 1.0 + i as Float).collect();
+    let samples: Vec<Complex> = (0..40)
+        .map(|i| Complex::from_polar(1.0, (i * i) as f32 * 0.1))
+        .collect();
+    let src = stream_of(&samples);
+
+    let channels = [1, 3];
+    let mut channelizer = Channelizer::new(src, &taps, bins, decimation, &channels);
+    channelizer.work().expect("Channelizing failed");
+
+    for (bin, out) in channels.iter().zip(channelizer.out()) {
+        let (out, _) = out.read_buf().expect("Stream not readable");
+        assert_eq!(out.len(), 14);
+        // frequency shift followed by FIR filter and decimation
+        for (n, y) in out.iter().enumerate() {
+            let t = n * decimation;
+            let expected: Complex = (0..=t.min(taps.len() - 1))
+                .map(|m| {
+                    let phase = -2.0 * std::f32::consts::PI * (bin * (t - m)) as f32 / bins as f32;
+                    taps[m] * samples[t - m] * Complex::from_polar(1.0, phase)
+                })
+                .sum();
+            assert!((y - expected).norm() < 1e-4, "{} != {}", y, expected);
+        }
+    }
+}
+
+#[test]
+fn test_bins() {
+    // channels at whole MHz, center frequency between two channels
+    assert_eq!(bins(4000, &[-500, 500, 1500]), Some((8, vec![7, 1, 3])));
+    assert_eq!(bins(20000, &[0, 3000, -2000]), Some((20, vec![0, 3, 18])));
+    assert_eq!(bins(20000, &[0, 1001]), None);
+}
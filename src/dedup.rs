@@ -0,0 +1,204 @@
+use crate::deframer::{Duplicates, NrfFrame};
+use crate::eof::{self, Ends};
+use crate::sweep_source::Position;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp};
+use rustradio::Error;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+// frames considered to be copies of the same transmission
+struct Group {
+    frame: NrfFrame,
+    count: usize,
+    channels: BTreeSet<u8>,
+}
+
+impl Group {
+    // same address, packet ID and payload received within the time window
+    fn matches(&self, frame: &NrfFrame, window_samples: u64) -> bool {
+        self.frame.address == frame.address
+            && self.frame.payload == frame.payload
+            && self.frame.pcf.map(|pcf| pcf.pid) == frame.pcf.map(|pcf| pcf.pid)
+            && self.frame.sample_start.abs_diff(frame.sample_start) <= window_samples
+    }
+
+    fn into_frame(self) -> NrfFrame {
+        let mut frame = self.frame;
+        frame.duplicates = Some(Duplicates {
+            count: self.count,
+            channels: self.channels.into_iter().collect(),
+        });
+        frame
+    }
+}
+
+/// Merges copies of frames received multiple times on the same or adjacent channels
+///
+/// Copies are awaited until a frame starting a window later is received, or the input stream has
+/// advanced far enough past the window for any copy to have been decoded, so that the last frame
+/// of a burst is not held back until unrelated frames are received.
+pub struct Dedup {
+    src: NoCopyStreamp<NrfFrame>,
+    dst: NoCopyStreamp<NrfFrame>,
+    window_samples: u64,
+    // start of the latest frame received, no more copies are awaited of groups a window before
+    latest: u64,
+    // number of samples received so far, frames are decoded behind it
+    position: Position,
+    // largest delay between receiving the first sample of a frame and the frame itself, in samples
+    lag: u64,
+    // groups awaiting further copies, in order of arrival
+    pending: Vec<Group>,
+}
+
+impl Dedup {
+    pub fn new(
+        src: NoCopyStreamp<NrfFrame>,
+        window: Duration,
+        sample_rate: f32,
+        position: Position,
+    ) -> Self {
+        Self {
+            src,
+            dst: NoCopyStream::newp(),
+            window_samples: (window.as_secs_f32() * sample_rate) as u64,
+            latest: 0,
+            position,
+            lag: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+}
+
+impl Block for Dedup {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let mut received = false;
+        while let Some((v, _tags)) = self.src.pop() {
+            received = true;
+            let position = *self.position.lock().unwrap();
+            self.lag = self.lag.max(position.saturating_sub(v.sample_start));
+            self.latest = self.latest.max(v.sample_start);
+            match self
+                .pending
+                .iter_mut()
+                .find(|g| g.matches(&v, self.window_samples))
+            {
+                Some(group) => {
+                    group.count += 1;
+                    group.channels.insert(v.channel);
+                }
+                None => self.pending.push(Group {
+                    count: 1,
+                    channels: BTreeSet::from([v.channel]),
+                    frame: v,
+                }),
+            }
+        }
+
+        // all remaining groups are complete at the end of the input, copies on other channels may
+        // be delayed by more than the frames seen so far, so twice the delay is awaited
+        let ended = !received && self.src.ended();
+        let position = *self.position.lock().unwrap();
+        let (done, pending): (Vec<Group>, Vec<Group>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|g| {
+                let end = g.frame.sample_start + self.window_samples;
+                ended || end < self.latest || end + 2 * self.lag < position
+            });
+        self.pending = pending;
+
+        let flushed = !done.is_empty();
+        for group in done {
+            self.dst.push(group.into_frame(), &[]);
+        }
+
+        if ended {
+            self.dst.end();
+            Ok(BlockRet::EOF)
+        } else if received || flushed {
+            Ok(BlockRet::Ok)
+        } else {
+            Ok(BlockRet::Noop)
+        }
+    }
+}
+
+impl BlockEOF for Dedup {
+    fn eof(&mut self) -> bool {
+        self.pending.is_empty() && eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for Dedup {
+    fn block_name(&self) -> &str {
+        "Dedup"
+    }
+}
+
+#[test]
+fn test_dedup() {
+    let src = NoCopyStream::newp();
+    let frame = |channel, sample_start, payload| NrfFrame {
+        channel,
+        address: vec![1, 2, 3],
+        payload: vec![payload],
+        sample_start,
+        ..Default::default()
+    };
+    src.push(frame(39, 1000, 1), &[]);
+    src.push(frame(47, 1010, 1), &[]);
+    src.push(frame(47, 1020, 2), &[]);
+    src.push(frame(39, 1100, 1), &[]);
+
+    // 1 ms at 100 kHz merges copies up to 100 samples apart
+    let position = Position::default();
+    *position.lock().unwrap() = 1150;
+    let mut dedup = Dedup::new(
+        src.clone(),
+        Duration::from_millis(1),
+        100_000.0,
+        position.clone(),
+    );
+    let out = dedup.out();
+    let flushed = || {
+        std::iter::from_fn(|| out.pop())
+            .map(|(f, _)| (f.payload[0], f.duplicates.unwrap()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(dedup.work().expect("Deduplication failed"), BlockRet::Ok);
+    assert!(flushed().is_empty());
+
+    // groups are complete once a frame starts more than a window later
+    src.push(frame(39, 1115, 1), &[]);
+    assert_eq!(dedup.work().expect("Deduplication failed"), BlockRet::Ok);
+    let counts = flushed();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].0, 1);
+    assert_eq!(counts[0].1.count, 3);
+    assert_eq!(counts[0].1.channels, vec![39, 47]);
+
+    // groups are complete once the input has advanced a window and twice the delay of frames,
+    // 150 samples, past them without further frames
+    assert_eq!(dedup.work().expect("Deduplication failed"), BlockRet::Noop);
+    assert!(flushed().is_empty());
+    *position.lock().unwrap() = 1500;
+    assert_eq!(dedup.work().expect("Deduplication failed"), BlockRet::Ok);
+    let counts = flushed();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].0, 2);
+    assert_eq!(counts[0].1.count, 1);
+
+    // remaining groups are complete at the end of the input
+    src.set_eof();
+    assert_eq!(dedup.work().expect("Deduplication failed"), BlockRet::EOF);
+    let counts = flushed();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].0, 1);
+    assert_eq!(counts[0].1.channels, vec![39]);
+    assert!(out.ended());
+}
@@ -1,15 +1,178 @@
+use crate::ble_deframer::BleHeader;
+use crate::eof;
+use crate::preamble_correlator;
 use bit_vec::BitVec;
 use crc::Algorithm;
+use itertools::Itertools;
 use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
 use rustradio::stream::{NoCopyStream, NoCopyStreamp, Streamp};
-use rustradio::Error;
+use rustradio::{Error, Float};
 use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
 
-const CRC16: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740);
-const CRC16_SB: crc::Crc<u16> = crc::Crc::<u16>::new(&Algorithm {
+pub const CRC16: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740);
+pub const CRC16_SB: crc::Crc<u16> = crc::Crc::<u16>::new(&Algorithm {
     init: 0x3c18, // custom init to account for left padding due to 9-bit PCF field.
     ..crc::CRC_16_IBM_3740
 });
+// 1-byte CRC of nRF24L01+, polynomial x^8 + x^2 + x + 1
+const CRC8_ALGORITHM: Algorithm<u8> = Algorithm {
+    width: 8,
+    poly: 0x07,
+    init: 0xff,
+    refin: false,
+    refout: false,
+    xorout: 0x00,
+    check: 0xfb,
+    residue: 0x00,
+};
+pub const CRC8: crc::Crc<u8> = crc::Crc::<u8>::new(&CRC8_ALGORITHM);
+pub const CRC8_SB: crc::Crc<u8> = crc::Crc::<u8>::new(&Algorithm {
+    init: 0x90, // custom init to account for left padding due to 9-bit PCF field.
+    ..CRC8_ALGORITHM
+});
+
+// CRC checksum of the given length in bytes (0-2), messages with ShockBurst PCF are left padded
+// to a multiple of 8 bits
+pub fn checksum(data: &[u8], crc_len: usize, shockburst: bool) -> u16 {
+    match (crc_len, shockburst) {
+        (0, _) => 0,
+        (1, false) => CRC8.checksum(data) as u16,
+        (1, true) => CRC8_SB.checksum(data) as u16,
+        (_, false) => CRC16.checksum(data),
+        (_, true) => CRC16_SB.checksum(data),
+    }
+}
+
+/// CRC checksum of the given length in bytes (1-2) computed incrementally, to check messages of
+/// increasing length without starting over
+#[derive(Clone)]
+pub enum Digest {
+    Crc8(crc::Digest<'static, u8>),
+    Crc16(crc::Digest<'static, u16>),
+}
+
+impl Digest {
+    pub fn new(crc_len: usize, shockburst: bool) -> Self {
+        match (crc_len, shockburst) {
+            (1, false) => Self::Crc8(CRC8.digest()),
+            (1, true) => Self::Crc8(CRC8_SB.digest()),
+            (_, false) => Self::Crc16(CRC16.digest()),
+            (_, true) => Self::Crc16(CRC16_SB.digest()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc8(digest) => digest.update(data),
+            Self::Crc16(digest) => digest.update(data),
+        }
+    }
+
+    /// Checksum of the message so far
+    pub fn checksum(&self) -> u16 {
+        match self.clone() {
+            Self::Crc8(digest) => digest.finalize() as u16,
+            Self::Crc16(digest) => digest.finalize(),
+        }
+    }
+}
+
+// change of the CRC checksum of the given length in bytes (1-2) caused by flipping each bit of a
+// message of the given length in bits, followed by the change caused by flipping each bit of the
+// checksum itself
+fn syndromes(message_len: usize, crc_len: usize) -> Vec<u16> {
+    let (width, poly) = match crc_len {
+        1 => (8, CRC8_ALGORITHM.poly as u16),
+        _ => (16, crc::CRC_16_IBM_3740.poly),
+    };
+    let mask = u16::MAX >> (16 - width);
+    // flipping a bit followed by n bits changes the checksum by the polynomial shifted n times
+    let mut syndromes: Vec<u16> = std::iter::successors(Some(poly), |s| {
+        Some(match s >> (width - 1) {
+            1 => (s << 1 ^ poly) & mask,
+            _ => s << 1 & mask,
+        })
+    })
+    .take(message_len)
+    .collect();
+    syndromes.reverse();
+    syndromes.extend((0..width).rev().map(|i| 1 << i));
+    syndromes
+}
+
+// finds the only bit among the given positions whose syndrome equals the given syndrome
+// pairs of bits are not searched, as the generator polynomial of the 2-byte CRC is a codeword of
+// weight 4, so that its Hamming distance is 4 at any frame length and distinct pairs of bits may
+// share their syndrome, let alone frames with more errors matching some pair
+fn find_error(syndrome: u16, syndromes: &[u16], positions: Range<usize>) -> Option<usize> {
+    let mut singles = positions.filter(|p| syndromes[*p] == syndrome);
+    match (singles.next(), singles.next()) {
+        (Some(p), None) => Some(p),
+        _ => None,
+    }
+}
+
+// number of least confident bits whose combinations are tried to correct a frame with a 2-byte
+// CRC, limited as each combination may pass the check by chance
+const CHASE_BITS: usize = 8;
+
+// finds the combinations of the given number of least confident bits among the given positions
+// whose syndromes add up to the given syndrome, and returns the one of least total confidence
+fn find_weak_errors(
+    syndrome: u16,
+    syndromes: &[u16],
+    confidence: &[Float],
+    positions: Range<usize>,
+    candidates: usize,
+) -> Option<Vec<usize>> {
+    let mut weakest: Vec<usize> = positions.collect();
+    weakest.sort_by(|a, b| confidence[*a].total_cmp(&confidence[*b]));
+    weakest.truncate(candidates);
+    // positions of the bits flipped by a combination
+    let flipped = |combination: usize| {
+        weakest
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| combination >> i & 1 == 1)
+            .map(|(_, p)| *p)
+    };
+    let total = |combination: usize| flipped(combination).map(|e| confidence[e]).sum::<Float>();
+    let best = (1..1usize << weakest.len())
+        .filter(|combination| {
+            flipped(*combination).fold(0, |acc, e| acc ^ syndromes[e]) == syndrome
+        })
+        .min_by(|a, b| total(*a).total_cmp(&total(*b)))?;
+    Some(flipped(best).sorted().collect())
+}
+
+// received bits along with the magnitudes of their soft symbols, the confidence in each bit
+#[derive(Debug, Clone)]
+struct SoftBits {
+    bits: BitVec,
+    confidence: Vec<Float>,
+}
+
+impl SoftBits {
+    // empty buffer starting with the given number of padding bits, which are certain
+    fn with_padding(capacity: usize, padding: usize) -> Self {
+        let mut bits = BitVec::with_capacity(capacity);
+        bits.grow(padding, false);
+        let mut confidence = Vec::with_capacity(capacity);
+        confidence.resize(padding, Float::INFINITY);
+        Self { bits, confidence }
+    }
+
+    fn push(&mut self, bit: bool, confidence: Float) {
+        self.bits.push(bit);
+        self.confidence.push(confidence);
+    }
+
+    fn len(&self) -> usize {
+        self.bits.len()
+    }
+}
 
 #[derive(Debug, Clone)]
 enum NrfDecoder {
@@ -17,13 +180,15 @@ enum NrfDecoder {
     // synchronizing on preamble
     Sync(usize, bool),
     // matching and receiving address
-    RecvAddr(BitVec, BitVec),
+    RecvAddr(SoftBits, BitVec),
     // matching and receiving PCF header
-    RecvHeader(BitVec, BitVec),
+    RecvHeader(SoftBits, BitVec),
     // receiving payload
-    RecvPayload(usize, BitVec),
+    RecvPayload(usize, SoftBits),
     // matching CRC header
-    CheckCrc(BitVec, BitVec),
+    CheckCrc(SoftBits, BitVec),
+    // receiving the remaining bits of an invalid CRC checksum to correct bit errors
+    RecvCrc(usize, SoftBits),
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +201,61 @@ pub struct NrfConfig {
     address_prefix: BitVec,
     payload_len: Option<usize>,
     shockburst: bool,
+    // length of CRC checksum in bytes (0-2)
+    crc_len: usize,
+    // correct single bit errors of frames with 2-byte CRC whose full address is known
+    error_correction: bool,
+    // correct bit errors of frames whose full address is known by flipping least confident bits
+    soft_decision: bool,
+    // emit frames of which the full address is known even if their CRC check failed
+    report_crc_errors: bool,
+    // used to map positions of bits to positions of frames and timestamps
+    timing: StreamTiming,
+}
+
+/// Relation between the demodulated bit stream and the input sample stream
+#[derive(Debug, Clone)]
+pub struct StreamTiming {
+    pub sample_rate: f64,
+    pub samples_per_symbol: f64,
+    // delay of the demodulated stream relative to the input stream in samples, e.g. due to filters
+    pub delay: f64,
+    // number of input samples per sample of the demodulated stream
+    pub decimation: f64,
+    // wall-clock time of the first input sample
+    pub start_time: Option<SystemTime>,
+}
+
+impl Default for StreamTiming {
+    fn default() -> Self {
+        StreamTiming {
+            sample_rate: 1.0,
+            samples_per_symbol: 1.0,
+            delay: 0.0,
+            decimation: 1.0,
+            start_time: None,
+        }
+    }
+}
+
+impl StreamTiming {
+    // position within the demodulated stream of the bit transmitted the given number of bits
+    // before the bit at the given position
+    pub fn preceding(&self, pos: u64, bits: u64) -> u64 {
+        (pos as f64 - bits as f64 * self.samples_per_symbol / self.decimation)
+            .max(0.0)
+            .round() as u64
+    }
+
+    // position within the input stream of the sample at the given position of the demodulated stream
+    pub fn sample_index(&self, pos: u64) -> u64 {
+        (pos as f64 * self.decimation - self.delay).max(0.0) as u64
+    }
+
+    pub fn timestamp(&self, sample_index: u64) -> Option<SystemTime> {
+        self.start_time
+            .map(|t| t + Duration::from_secs_f64(sample_index as f64 / self.sample_rate))
+    }
 }
 
 impl NrfConfig {
@@ -56,6 +276,11 @@ impl NrfConfig {
             address_prefix: BitVec::from_bytes(&revered_address_prefix),
             payload_len: Some(payload_len),
             shockburst: false,
+            crc_len: 2,
+            error_correction: false,
+            soft_decision: false,
+            report_crc_errors: false,
+            timing: StreamTiming::default(),
         }
     }
     pub fn shockburst(
@@ -75,15 +300,63 @@ impl NrfConfig {
             address_prefix: BitVec::from_bytes(&revered_address_prefix),
             payload_len,
             shockburst: true,
+            crc_len: 2,
+            error_correction: false,
+            soft_decision: false,
+            report_crc_errors: false,
+            timing: StreamTiming::default(),
         }
     }
 
+    pub fn timing(mut self, timing: StreamTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn crc_length(mut self, crc_len: usize) -> Self {
+        self.crc_len = crc_len;
+        self
+    }
+
+    pub fn error_correction(mut self, error_correction: bool) -> Self {
+        self.error_correction = error_correction;
+        self
+    }
+
+    pub fn soft_decision(mut self, soft_decision: bool) -> Self {
+        self.soft_decision = soft_decision;
+        self
+    }
+
+    pub fn report_crc_errors(mut self, report_crc_errors: bool) -> Self {
+        self.report_crc_errors = report_crc_errors;
+        self
+    }
+
+    // errors are only corrected or reported if the full address is known, as there are too many
+    // false positives otherwise
+    fn handles_crc_errors(&self) -> bool {
+        (self.error_correction || self.soft_decision || self.report_crc_errors)
+            && self.crc_len > 0
+            && self.address_prefix.len() == self.address_len * 8
+    }
+
+    // patterns of preamble and address prefix to search for frames
+    pub fn sync_patterns(&self) -> Vec<Vec<bool>> {
+        let prefix: Vec<bool> = self.address_prefix.iter().rev().collect();
+        preamble_correlator::sync_patterns(&prefix)
+    }
+
+    // maximum possible length of frame in bits including preamble
+    pub fn max_frame_bits(&self) -> usize {
+        8 + self.max_length_bytes() * 8
+    }
+
     // maximum possible length of message in bytes used for buffer initialization to avoid reallocations
     fn max_length_bytes(&self) -> usize {
-        let crc_len = 2;
         let payload_len = self.payload_len.unwrap_or(32);
         let header_len = if self.shockburst { 2 } else { 0 };
-        self.address_len + header_len + payload_len + crc_len
+        self.address_len + header_len + payload_len + self.crc_len
     }
 
     // prepended length of padding in bits required for messages which are not byte-aligned
@@ -118,44 +391,60 @@ impl NrfConfig {
 
 impl NrfDecoder {
     // called when parsing failed, drops the first bit of the received data and re-attempts parsing
+    // pos is the position of the last bit of the received data within the demodulated stream
     fn drop_bit_and_resync(
         config: &NrfConfig,
         out: &NoCopyStreamp<NrfFrame>,
-        data: BitVec,
+        data: SoftBits,
+        pos: u64,
     ) -> NrfDecoder {
-        data.iter()
+        let len = (data.len() - config.padding_length_bits()) as u64;
+        data.bits
+            .iter()
+            .zip(data.confidence.iter())
             .skip(config.padding_length_bits())
-            .fold(NrfDecoder::Sync(7, !data[config.padding_length_bits()]), |d, b| {
-                d.push_bit(config, out, b)
-            })
+            .zip((0..len).rev())
+            .fold(
+                NrfDecoder::Sync(7, !data.bits[config.padding_length_bits()]),
+                |d, ((b, c), i)| d.push_bit(config, out, b, *c, config.timing.preceding(pos, i)),
+            )
     }
 
-    fn push_bit(self, config: &NrfConfig, out: &NoCopyStreamp<NrfFrame>, bit: bool) -> NrfDecoder {
+    // pushes a single bit with the given confidence received at position pos within the
+    // demodulated stream
+    fn push_bit(
+        self,
+        config: &NrfConfig,
+        out: &NoCopyStreamp<NrfFrame>,
+        bit: bool,
+        confidence: Float,
+        pos: u64,
+    ) -> NrfDecoder {
         match self {
             NrfDecoder::Empty => NrfDecoder::Sync(1, bit),
 
             // received preamble, check first bit of address
             NrfDecoder::Sync(8, prev) if prev != bit => {
-                let mut buffer = BitVec::with_capacity(config.max_length_bytes() * 8);
-                buffer.grow(config.padding_length_bits(), false);
+                let buffer = SoftBits::with_padding(
+                    config.max_length_bytes() * 8,
+                    config.padding_length_bits(),
+                );
                 NrfDecoder::RecvAddr(buffer, config.address_prefix.clone())
-                    .push_bit(config, out, bit)
+                    .push_bit(config, out, bit, confidence, pos)
             }
 
             // receiving preamble
-            NrfDecoder::Sync(recv, prev) if prev != bit => {
-                NrfDecoder::Sync(recv + 1, bit)
-            }
+            NrfDecoder::Sync(recv, prev) if prev != bit => NrfDecoder::Sync(recv + 1, bit),
 
             // preamble mismatch, reset
             NrfDecoder::Sync(_, _) => NrfDecoder::Sync(1, bit),
 
             // receiving address
             NrfDecoder::RecvAddr(mut data, mut prefix) => {
-                data.push(bit);
+                data.push(bit, confidence);
                 match prefix.pop() {
                     // invalid prefix
-                    Some(b) if b != bit => NrfDecoder::drop_bit_and_resync(config, out, data),
+                    Some(b) if b != bit => NrfDecoder::drop_bit_and_resync(config, out, data, pos),
                     // insufficient length
                     _ if data.len() < config.address_len * 8 + config.padding_length_bits() => {
                         NrfDecoder::RecvAddr(data, prefix)
@@ -172,15 +461,16 @@ impl NrfDecoder {
 
             // receive ShockBurst header
             NrfDecoder::RecvHeader(mut data, mut length) => {
-                data.push(bit);
+                data.push(bit, confidence);
                 match length.pop() {
                     // invalid length
-                    Some(b) if b != bit => NrfDecoder::drop_bit_and_resync(config, out, data),
+                    Some(b) if b != bit => NrfDecoder::drop_bit_and_resync(config, out, data, pos),
                     _ if data.len() < (2 + config.address_len) * 8 => {
                         NrfDecoder::RecvHeader(data, length)
                     }
                     _ => {
                         let payload_len = data
+                            .bits
                             .iter()
                             .skip(config.address_len * 8 + config.padding_length_bits())
                             .take(6)
@@ -191,7 +481,7 @@ impl NrfDecoder {
                                 data,
                             )
                         } else {
-                            NrfDecoder::drop_bit_and_resync(config, out, data)
+                            NrfDecoder::drop_bit_and_resync(config, out, data, pos)
                         }
                     }
                 }
@@ -199,90 +489,328 @@ impl NrfDecoder {
 
             // receiving data
             NrfDecoder::RecvPayload(message_len, mut data) => {
-                data.push(bit);
+                data.push(bit, confidence);
                 if data.len() < message_len {
                     NrfDecoder::RecvPayload(message_len, data)
+                } else if config.crc_len == 0 {
+                    NrfDecoder::emit(config, out, data.bits, pos, None, None)
                 } else {
-                    let crc = if config.shockburst { CRC16_SB } else { CRC16 };
-                    let d = crc.checksum(&data.to_bytes());
-                    NrfDecoder::CheckCrc(data, BitVec::from_bytes(&d.reverse_bits().to_be_bytes()))
+                    let d = checksum(&data.bits.to_bytes(), config.crc_len, config.shockburst);
+                    let mut crc = BitVec::from_bytes(&d.reverse_bits().to_be_bytes());
+                    crc.truncate(config.crc_len * 8);
+                    NrfDecoder::CheckCrc(data, crc)
                 }
             }
 
             // checking CRC checksum
             NrfDecoder::CheckCrc(mut data, mut payload) => {
-                data.push(bit);
+                data.push(bit, confidence);
                 match payload.pop() {
+                    // invalid checksum, received in full to correct or report bit errors
+                    Some(b) if b != bit && config.handles_crc_errors() => match payload.len() {
+                        0 => NrfDecoder::correct(config, out, data, pos),
+                        remaining => NrfDecoder::RecvCrc(remaining, data),
+                    },
                     // invalid checksum
-                    Some(b) if b != bit => NrfDecoder::drop_bit_and_resync(config, out, data),
+                    Some(b) if b != bit => NrfDecoder::drop_bit_and_resync(config, out, data, pos),
                     // crc checksum not fully received
                     _ if !payload.is_empty() => NrfDecoder::CheckCrc(data, payload),
                     // passed
-                    _ => {
-                        let mut payload = data.split_off(
-                            config.padding_length_bits()
-                                + config.address_len * 8
-                                + config.header_length_bits(),
-                        );
-                        let mut address = data.split_off(config.padding_length_bits());
-                        address.truncate(config.address_len * 8); // drop PCF
-                        payload.truncate(payload.len() - 16); // drop CRC
-                        out.push(
-                            NrfFrame {
-                                channel: config.channel,
-                                address: address.to_bytes(),
-                                payload: payload.to_bytes(),
-                            },
-                            &[],
-                        );
-                        NrfDecoder::Empty
-                    }
+                    _ => NrfDecoder::emit(config, out, data.bits, pos, None, None),
                 }
             }
+
+            // receiving invalid checksum
+            NrfDecoder::RecvCrc(remaining, mut data) => {
+                data.push(bit, confidence);
+                match remaining {
+                    1 => NrfDecoder::correct(config, out, data, pos),
+                    _ => NrfDecoder::RecvCrc(remaining - 1, data),
+                }
+            }
+        }
+    }
+
+    // called when the frame including an invalid CRC checksum has been fully received, flips the
+    // bits explaining the difference of the checksums and emits the frame if successful, or emits
+    // the frame as received if CRC errors are reported
+    // least confident bits are tried first, then single bit errors
+    fn correct(
+        config: &NrfConfig,
+        out: &NoCopyStreamp<NrfFrame>,
+        mut data: SoftBits,
+        pos: u64,
+    ) -> NrfDecoder {
+        let message_len = data.len() - config.crc_len * 8;
+        let mut message = data.bits.clone();
+        message.truncate(message_len);
+        let received = data
+            .bits
+            .iter()
+            .skip(message_len)
+            .fold(0, |acc, bit| (acc << 1) | bit as u16);
+        let computed = checksum(&message.to_bytes(), config.crc_len, config.shockburst);
+        let syndrome = computed ^ received;
+        // address has been matched and the payload length determined the frame length, so both
+        // are known to be received correctly
+        let length_bits = if config.shockburst { 6 } else { 0 };
+        let first = config.padding_length_bits() + config.address_len * 8 + length_bits;
+        let syndromes = syndromes(message_len, config.crc_len);
+        // a 1-byte CRC is too short to tell bit errors apart from garbage
+        let correctable = config.crc_len == 2;
+        let errors = (config.soft_decision && correctable)
+            .then(|| {
+                find_weak_errors(
+                    syndrome,
+                    &syndromes,
+                    &data.confidence,
+                    first..data.len(),
+                    CHASE_BITS,
+                )
+            })
+            .flatten()
+            .or_else(|| {
+                (config.error_correction && correctable)
+                    .then(|| find_error(syndrome, &syndromes, first..data.len()))
+                    .flatten()
+                    .map(|e| vec![e])
+            });
+        match errors {
+            Some(errors) => {
+                for e in &errors {
+                    data.bits.set(*e, !data.bits[*e]);
+                }
+                let corrected = errors
+                    .iter()
+                    .map(|e| e - config.padding_length_bits())
+                    .collect();
+                NrfDecoder::emit(config, out, data.bits, pos, Some(corrected), None)
+            }
+            None if config.report_crc_errors => {
+                NrfDecoder::emit(config, out, data.bits, pos, None, Some(computed as u32))
+            }
+            None => NrfDecoder::drop_bit_and_resync(config, out, data, pos),
+        }
+    }
+
+    // called when the frame has been fully received and passed the CRC check, if any, or failed
+    // it with the given computed checksum
+    // pos is the position of the last bit of the frame within the demodulated stream
+    fn emit(
+        config: &NrfConfig,
+        out: &NoCopyStreamp<NrfFrame>,
+        mut data: BitVec,
+        pos: u64,
+        corrected: Option<Vec<usize>>,
+        computed_crc: Option<u32>,
+    ) -> NrfDecoder {
+        let pcf = config.shockburst.then(|| {
+            Pcf::from_bits(
+                data.iter()
+                    .skip(config.padding_length_bits() + config.address_len * 8)
+                    .take(9)
+                    .fold(0, |acc, bit| (acc << 1) | bit as u16),
+            )
+        });
+        // frame length in bits including 8-bit preamble
+        let frame_len = (data.len() - config.padding_length_bits() + 8) as u64;
+        let start = config.timing.preceding(pos, frame_len - 1);
+        let sample_start = config.timing.sample_index(start);
+        let sample_count = frame_len as f64 * config.timing.samples_per_symbol;
+        let mut payload = data.split_off(
+            config.padding_length_bits() + config.address_len * 8 + config.header_length_bits(),
+        );
+        let mut address = data.split_off(config.padding_length_bits());
+        address.truncate(config.address_len * 8); // drop PCF
+        let crc = payload
+            .split_off(payload.len() - config.crc_len * 8)
+            .iter()
+            .fold(0, |acc, bit| (acc << 1) | bit as u32);
+        out.push(
+            NrfFrame {
+                channel: config.channel,
+                address: address.to_bytes(),
+                payload: payload.to_bytes(),
+                pcf,
+                crc: (config.crc_len > 0).then_some(crc),
+                computed_crc,
+                sample_start,
+                sample_count: sample_count as u64,
+                timestamp: config.timing.timestamp(sample_start),
+                duplicates: None,
+                signal: None,
+                ble: None,
+                framing: None,
+                corrected,
+            },
+            &[],
+        );
+        NrfDecoder::Empty
+    }
+}
+
+// center frequency of a channel in Hz
+pub fn channel_freq(channel: u8) -> f64 {
+    2_400_000_000.0 + 1_000_000.0 * channel as f64
+}
+
+/// Packet control field of Enhanced ShockBurst packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pcf {
+    // payload length in bytes (6 bits)
+    pub length: u8,
+    // packet ID, incremented for each new packet (2 bits)
+    pub pid: u8,
+    // receiver is asked not to acknowledge the packet
+    pub no_ack: bool,
+}
+
+impl Pcf {
+    // parses the 9-bit PCF, most significant bit first
+    pub fn from_bits(bits: u16) -> Self {
+        Pcf {
+            length: (bits >> 3) as u8 & 0x3f,
+            pid: (bits >> 1) as u8 & 0x03,
+            no_ack: bits & 0x01 == 1,
         }
     }
 }
 
-#[derive(Debug, Default)]
+/// Framing of a frame inferred by promiscuous deframing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Framing {
+    // address length in bytes, assumed for frames without PCF
+    pub address_len: usize,
+    // frame has an Enhanced ShockBurst PCF
+    pub shockburst: bool,
+}
+
+/// Copies of a frame merged by duplicate detection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicates {
+    // number of received copies including the frame itself
+    pub count: usize,
+    // channels the copies were received on
+    pub channels: Vec<u8>,
+}
+
+/// Signal quality of a received frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signal {
+    // mean power in dB relative to full scale
+    pub power: f32,
+    // mean power relative to the noise floor in dB
+    pub snr: f32,
+    // carrier frequency offset relative to the channel frequency in Hz
+    pub freq_offset: f32,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct NrfFrame {
     pub channel: u8,
     pub address: Vec<u8>,
     pub payload: Vec<u8>,
+    // packet control field, only present for Enhanced ShockBurst packets
+    pub pcf: Option<Pcf>,
+    // received CRC checksum, absent if CRC is disabled
+    pub crc: Option<u32>,
+    // computed CRC checksum, only present if the CRC check failed and CRC errors are reported
+    pub computed_crc: Option<u32>,
+    // approximate position of the first preamble bit within the input sample stream
+    pub sample_start: u64,
+    // approximate length of the frame including preamble in samples
+    pub sample_count: u64,
+    // wall-clock time of the first preamble bit, if the start time of the stream is known
+    pub timestamp: Option<SystemTime>,
+    // merged copies, only present if duplicate detection is enabled
+    pub duplicates: Option<Duplicates>,
+    // signal quality, measured after deframing
+    pub signal: Option<Signal>,
+    // PDU header, only present for BLE advertising packets
+    pub ble: Option<BleHeader>,
+    // inferred framing, only present for frames received in promiscuous mode
+    pub framing: Option<Framing>,
+    // positions of bits flipped by error correction, counted from the first address bit
+    pub corrected: Option<Vec<usize>>,
 }
 
 impl Display for NrfFrame {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(
-            f,
-            "{:3} {} {}",
-            self.channel,
-            hex::encode(&self.address),
-            hex::encode(&self.payload)
-        )
+        write!(f, "{:3} {}", self.channel, hex::encode(&self.address))?;
+        if let Some(pcf) = &self.pcf {
+            write!(f, " {:3} {:6}", pcf.pid, pcf.no_ack as u8)?;
+        }
+        if let Some(ble) = &self.ble {
+            write!(f, " {}", ble.pdu_type_name())?;
+        }
+        write!(f, " {}", hex::encode(&self.payload))?;
+        if let Some(duplicates) = &self.duplicates {
+            write!(
+                f,
+                " ({}x on {})",
+                duplicates.count,
+                duplicates.channels.iter().map(|c| c.to_string()).join(",")
+            )?;
+        }
+        if let Some(corrected) = &self.corrected {
+            write!(
+                f,
+                " [corrected bits {}]",
+                corrected.iter().map(|p| p.to_string()).join(",")
+            )?;
+        }
+        if let (Some(crc), Some(computed_crc)) = (self.crc, self.computed_crc) {
+            write!(
+                f,
+                " [CRC error, received {:#x}, computed {:#x}]",
+                crc, computed_crc
+            )?;
+        }
+        if let Some(framing) = &self.framing {
+            write!(
+                f,
+                " [{}, {} byte address, {} byte payload]",
+                if framing.shockburst { "ESB" } else { "fixed" },
+                framing.address_len,
+                self.payload.len()
+            )?;
+        }
+        Ok(())
     }
 }
 
 impl NrfFrame {
-    #[cfg(test)]
-    fn encode_fixed(&self) -> BitVec {
+    // frame passed the CRC check, or was received without CRC
+    pub fn crc_ok(&self) -> bool {
+        self.computed_crc.is_none()
+    }
+
+    /// Bits of the frame without PCF as transmitted, starting with the preamble and followed by
+    /// a CRC of the given length in bytes
+    ///
+    /// Used to generate signals as well as to test decoding.
+    pub fn encode_fixed(&self, crc_len: usize) -> BitVec {
         let mut bits = BitVec::from_bytes(&self.address);
         bits.append(&mut BitVec::from_bytes(&self.payload));
-        let crc = CRC16.checksum(&bits.to_bytes());
-        bits.append(&mut BitVec::from_bytes(&crc.to_be_bytes()));
+        let crc = checksum(&bits.to_bytes(), crc_len, false);
+        bits.append(&mut BitVec::from_bytes(&crc.to_be_bytes()[2 - crc_len..]));
         for _ in 0..8 {
             bits.insert(0, !bits[0]);
         }
         bits
     }
-    #[cfg(test)]
-    fn encode_dynamic(&self) -> BitVec {
+
+    /// Bits of the frame with PCF as transmitted, starting with the preamble and followed by a
+    /// CRC of the given length in bytes
+    pub fn encode_dynamic(&self, crc_len: usize) -> BitVec {
         let mut bits = BitVec::from_elem(7, false);
         bits.append(&mut BitVec::from_bytes(&self.address));
         bits.append(&mut BitVec::from_bytes(&[self.payload.len() as u8]).split_off(2));
-        bits.grow(3, false);
+        let pcf = self.pcf.map_or(0, |pcf| pcf.pid << 1 | pcf.no_ack as u8);
+        bits.append(&mut BitVec::from_bytes(&[pcf]).split_off(5));
         bits.append(&mut BitVec::from_bytes(&self.payload));
-        let crc = CRC16_SB.checksum(&bits.to_bytes());
-        bits.append(&mut BitVec::from_bytes(&crc.to_be_bytes()));
+        let crc = checksum(&bits.to_bytes(), crc_len, true);
+        bits.append(&mut BitVec::from_bytes(&crc.to_be_bytes()[2 - crc_len..]));
         bits = bits.split_off(7);
         for _ in 0..8 {
             bits.insert(0, !bits[0]);
@@ -291,20 +819,27 @@ impl NrfFrame {
     }
 }
 
+/// Deframer of nRF24 frames, matching the bit stream against several configurations at once
 pub struct NrfDeframer {
-    src: Streamp<u8>,
+    // soft symbols, positive for ones, whose magnitudes are the confidence in the bits
+    src: Streamp<Float>,
+    // positions of the bits within the demodulated stream
+    positions: Streamp<u64>,
     dst: NoCopyStreamp<NrfFrame>,
-    config: NrfConfig,
-    state: NrfDecoder,
+    // every configuration with the state of its decoder
+    decoders: Vec<(NrfConfig, NrfDecoder)>,
 }
 
 impl NrfDeframer {
-    pub fn new(src: Streamp<u8>, config: NrfConfig) -> Self {
+    pub fn new(src: Streamp<Float>, positions: Streamp<u64>, configs: Vec<NrfConfig>) -> Self {
         Self {
             src,
+            positions,
             dst: NoCopyStream::newp(),
-            config,
-            state: NrfDecoder::Empty,
+            decoders: configs
+                .into_iter()
+                .map(|config| (config, NrfDecoder::Empty))
+                .collect(),
         }
     }
 
@@ -318,20 +853,41 @@ impl Block for NrfDeframer {
     fn work(&mut self) -> Result<BlockRet, Error> {
         let ti = self.src.clone();
         let (input, _tags) = ti.read_buf()?;
-        if input.is_empty() {
-            return Ok(BlockRet::Noop);
+        let tp = self.positions.clone();
+        let (positions, tags) = tp.read_buf()?;
+        let n = std::cmp::min(input.len(), positions.len());
+        if n == 0 {
+            drop((input, positions));
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
         }
 
-        self.state = input
-            .iter()
-            .copied()
-            .map(|b| b != 0)
-            .fold(self.state.clone(), |s, b| {
-                s.push_bit(&self.config, &self.out(), b)
-            });
+        // bits are passed to all decoders in turn, so that frames are output in order
+        let out = self.out();
+        let window_starts = preamble_correlator::window_starts(&tags);
+        for (i, (s, pos)) in input.iter().zip(positions.iter()).take(n).enumerate() {
+            // frames cut short by the end of a candidate window are not continued by the next one
+            if window_starts.binary_search(&i).is_ok() {
+                for (_, state) in self.decoders.iter_mut() {
+                    *state = NrfDecoder::Empty;
+                }
+            }
+            for (config, state) in self.decoders.iter_mut() {
+                *state = std::mem::replace(state, NrfDecoder::Empty).push_bit(
+                    config,
+                    &out,
+                    *s > 0.0,
+                    s.abs(),
+                    *pos,
+                );
+            }
+        }
 
-        let n = input.len();
         input.consume(n);
+        positions.consume(n);
         Ok(BlockRet::Ok)
     }
 }
@@ -342,7 +898,11 @@ impl BlockName for NrfDeframer {
     }
 }
 
-impl BlockEOF for NrfDeframer {}
+impl BlockEOF for NrfDeframer {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&(self.src.clone(), self.positions.clone()), &self.dst)
+    }
+}
 
 #[test]
 fn test_fixed() {
@@ -350,16 +910,40 @@ fn test_fixed() {
         channel: 39,
         address: vec![1, 2, 3, 4],
         payload: vec![5, 6, 7, 8, 9, 10],
+        ..Default::default()
     };
-    let config = NrfConfig::fixed_length(39, packet.address.len(), packet.payload.len(), &[]);
+    let timing = StreamTiming {
+        sample_rate: 1_000.0,
+        samples_per_symbol: 2.0,
+        delay: 4.0,
+        decimation: 1.0,
+        start_time: Some(SystemTime::UNIX_EPOCH),
+    };
+    let config =
+        NrfConfig::fixed_length(39, packet.address.len(), packet.payload.len(), &[]).timing(timing);
     let out = NoCopyStream::newp();
     let mut state = NrfDecoder::Empty;
-    for bit in packet.encode_fixed().iter() {
-        state = state.push_bit(&config, &out, bit);
+    // prepend noise to shift packet within stream
+    for (pos, bit) in [true, true, false, true, false, false]
+        .into_iter()
+        .chain(packet.encode_fixed(2).iter())
+        .enumerate()
+    {
+        state = state.push_bit(&config, &out, bit, 1.0, pos as u64 * 2);
     }
     let (out, _) = out.pop().expect("Parsing failed");
     assert_eq!(out.address, packet.address);
     assert_eq!(out.payload, packet.payload);
+    assert_eq!(
+        out.crc,
+        Some(CRC16.checksum(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]) as u32)
+    );
+    assert_eq!(out.sample_start, 6 * 2 - 4);
+    assert_eq!(out.sample_count, packet.encode_fixed(2).len() as u64 * 2);
+    assert_eq!(
+        out.timestamp,
+        Some(SystemTime::UNIX_EPOCH + Duration::from_millis(8))
+    );
 }
 
 #[test]
@@ -368,14 +952,346 @@ fn test_shockburst() {
         channel: 39,
         address: vec![1, 2, 3, 4],
         payload: vec![5, 6, 7, 8, 9, 10],
+        pcf: Some(Pcf {
+            length: 6,
+            pid: 2,
+            no_ack: true,
+        }),
+        ..Default::default()
     };
     let config = NrfConfig::shockburst(39, packet.address.len(), None, &[]);
     let out = NoCopyStream::newp();
     let mut state = NrfDecoder::Empty;
-    for bit in packet.encode_dynamic().iter() {
-        state = state.push_bit(&config, &out, bit);
+    for (pos, bit) in packet.encode_dynamic(2).iter().enumerate() {
+        state = state.push_bit(&config, &out, bit, 1.0, pos as u64);
     }
     let (out, _) = out.pop().expect("Parsing failed");
     assert_eq!(out.address, packet.address);
     assert_eq!(out.payload, packet.payload);
+    assert_eq!(out.pcf, packet.pcf);
+}
+
+#[test]
+fn test_crc_length() {
+    let packet = NrfFrame {
+        channel: 39,
+        address: vec![1, 2, 3],
+        payload: vec![4, 5],
+        pcf: Some(Pcf {
+            length: 2,
+            pid: 1,
+            no_ack: false,
+        }),
+        ..Default::default()
+    };
+    assert_eq!(CRC8.checksum(b"123456789"), 0xfb);
+    // after 7 bits of padding, the register holds the initial value without padding, so that a
+    // single set bit shifts it without feedback
+    assert_eq!(CRC8_SB.checksum(&[0x01]), 0xfe);
+    assert_eq!(CRC16_SB.checksum(&[0x01]), 0xfffe);
+    for (crc_len, sb) in [(1, false), (1, true), (2, false), (2, true)] {
+        let mut digest = Digest::new(crc_len, sb);
+        digest.update(b"1234");
+        assert_eq!(digest.checksum(), checksum(b"1234", crc_len, sb));
+        digest.update(b"56789");
+        assert_eq!(digest.checksum(), checksum(b"123456789", crc_len, sb));
+    }
+
+    let config = NrfConfig::fixed_length(39, 3, 2, &[]).crc_length(1);
+    let out = NoCopyStream::newp();
+    let mut state = NrfDecoder::Empty;
+    for (pos, bit) in packet.encode_fixed(1).iter().enumerate() {
+        state = state.push_bit(&config, &out, bit, 1.0, pos as u64);
+    }
+    let (frame, _) = out.pop().expect("Parsing with 1-byte CRC failed");
+    assert_eq!(frame.payload, packet.payload);
+    assert_eq!(frame.crc, Some(CRC8.checksum(&[1, 2, 3, 4, 5]) as u32));
+
+    let config = NrfConfig::shockburst(39, 3, None, &[1]).crc_length(0);
+    let mut state = NrfDecoder::Empty;
+    for (pos, bit) in packet.encode_dynamic(0).iter().enumerate() {
+        state = state.push_bit(&config, &out, bit, 1.0, pos as u64);
+    }
+    let (frame, _) = out.pop().expect("Parsing without CRC failed");
+    assert_eq!(frame.payload, packet.payload);
+    assert_eq!(frame.pcf, packet.pcf);
+    assert_eq!(frame.crc, None);
+}
+
+#[test]
+fn test_multiple_targets() {
+    use crate::test_util::stream_with_positions;
+
+    let esb = NrfFrame {
+        address: vec![0xe7, 0xe7, 0xe7],
+        payload: vec![1, 2, 3],
+        pcf: Some(Pcf {
+            length: 3,
+            pid: 0,
+            no_ack: false,
+        }),
+        ..Default::default()
+    };
+    let fixed = NrfFrame {
+        address: vec![0x07, 0x07, 0x07, 0x07],
+        payload: vec![4, 5],
+        ..Default::default()
+    };
+    let symbols: Vec<Float> = fixed
+        .encode_fixed(1)
+        .iter()
+        .chain(esb.encode_dynamic(2).iter())
+        .chain(fixed.encode_fixed(1).iter())
+        .map(|b| if b { 1.0 } else { -1.0 })
+        .collect();
+    let (src, positions) = stream_with_positions(&symbols);
+
+    let configs = vec![
+        NrfConfig::shockburst(39, 3, None, &[0xe7]),
+        NrfConfig::fixed_length(39, 4, 2, &[0x07]).crc_length(1),
+    ];
+    let mut deframer = NrfDeframer::new(src, positions, configs);
+    deframer.work().expect("Deframing failed");
+
+    let out = deframer.out();
+    for expected in [&fixed, &esb, &fixed] {
+        let (frame, _) = out.pop().expect("Frame not found");
+        assert_eq!(frame.address, expected.address);
+        assert_eq!(frame.payload, expected.payload);
+    }
+    assert!(out.pop().is_none());
+}
+
+#[test]
+fn test_window_start() {
+    use crate::test_util::stream_of;
+    use rustradio::stream::{Tag, TagValue};
+
+    let frame = |payload| NrfFrame {
+        address: vec![1, 2, 3],
+        payload,
+        ..Default::default()
+    };
+    // a frame cut short after its address, followed by a candidate window of a full frame, which
+    // would otherwise be taken as payload without CRC
+    let cut = frame(vec![4, 5]).encode_fixed(0);
+    let full = frame(vec![6, 7]).encode_fixed(0);
+    let symbols: Vec<Float> = cut
+        .iter()
+        .take(8 + 24)
+        .chain(full.iter())
+        .map(|b| if b { 1.0 } else { -1.0 })
+        .collect();
+    let positions = rustradio::stream::Stream::newp();
+    let mut buf = positions.write_buf().expect("Stream not writable");
+    buf.fill_from_iter(0..symbols.len() as u64);
+    let tag = Tag::new(
+        8 + 24,
+        preamble_correlator::WINDOW_START.to_string(),
+        TagValue::Bool(true),
+    );
+    buf.produce(symbols.len(), &[tag]);
+
+    let config = NrfConfig::fixed_length(39, 3, 2, &[1, 2, 3]).crc_length(0);
+    let mut deframer = NrfDeframer::new(stream_of(&symbols), positions, vec![config]);
+    deframer.work().expect("Deframing failed");
+    let out = deframer.out();
+    let (received, _) = out.pop().expect("Frame not found");
+    assert_eq!(received.payload, vec![6, 7]);
+    assert!(out.pop().is_none());
+}
+
+#[test]
+fn test_error_correction() {
+    let packet = NrfFrame {
+        address: vec![1, 2, 3, 4],
+        payload: vec![5, 6, 7, 8],
+        pcf: Some(Pcf {
+            length: 4,
+            pid: 2,
+            no_ack: false,
+        }),
+        ..Default::default()
+    };
+    let receive = |config: &NrfConfig, bits: BitVec| {
+        let out = NoCopyStream::newp();
+        let mut state = NrfDecoder::Empty;
+        for (pos, bit) in bits.iter().enumerate() {
+            state = state.push_bit(config, &out, bit, 1.0, pos as u64);
+        }
+        out.pop().map(|(frame, _)| frame)
+    };
+    // flips bits counted from the first address bit, following the 8-bit preamble
+    let flip = |mut bits: BitVec, positions: &[usize]| {
+        for p in positions {
+            bits.set(8 + p, !bits[8 + p]);
+        }
+        bits
+    };
+
+    let config = NrfConfig::fixed_length(39, 4, 4, &[1, 2, 3, 4]).error_correction(true);
+    for errors in [vec![32], vec![40], vec![70]] {
+        let frame =
+            receive(&config, flip(packet.encode_fixed(2), &errors)).expect("Correction failed");
+        assert_eq!(frame.payload, packet.payload);
+        assert_eq!(
+            frame.crc,
+            Some(CRC16.checksum(&[1, 2, 3, 4, 5, 6, 7, 8]) as u32)
+        );
+        assert_eq!(frame.corrected, Some(errors));
+    }
+    // double bit errors are not corrected
+    assert!(receive(&config, flip(packet.encode_fixed(2), &[33, 50])).is_none());
+    // nor could they be, as the last message bit and the fourth CRC bit share their syndrome with
+    // the 11th and the last CRC bit, whatever the frame length
+    let s = syndromes(64, 2);
+    assert_eq!(s[63] ^ s[64 + 3], s[64 + 10] ^ s[64 + 15]);
+    // errors are not corrected without the full address
+    let config = NrfConfig::fixed_length(39, 4, 4, &[1, 2]).error_correction(true);
+    assert!(receive(&config, flip(packet.encode_fixed(2), &[40])).is_none());
+
+    // PID and payload of ESB frames, but not the payload length
+    let config = NrfConfig::shockburst(39, 4, None, &[1, 2, 3, 4]).error_correction(true);
+    let frame = receive(&config, flip(packet.encode_dynamic(2), &[38])).expect("Correction failed");
+    assert_eq!(frame.pcf, packet.pcf);
+    assert_eq!(frame.payload, packet.payload);
+    assert_eq!(frame.corrected, Some(vec![38]));
+    let frame = receive(&config, flip(packet.encode_dynamic(2), &[50])).expect("Correction failed");
+    assert_eq!(frame.payload, packet.payload);
+    assert_eq!(frame.corrected, Some(vec![50]));
+    // errors are not corrected with a 1-byte CRC
+    let config = config.crc_length(1);
+    assert!(receive(&config, flip(packet.encode_dynamic(1), &[46])).is_none());
+}
+
+#[test]
+fn test_uncorrectable() {
+    // frames of a matching address followed by random payload and CRC bits, which must rarely be
+    // turned into valid frames by correction
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut random = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    };
+    let address = vec![1, 2, 3, 4, 5];
+    let mut received = |config: &NrfConfig, len: usize| {
+        let out = NoCopyStream::newp();
+        for _ in 0..1000 {
+            let frame = NrfFrame {
+                address: address.clone(),
+                payload: (0..len).map(|_| random()).collect(),
+                ..Default::default()
+            };
+            let mut state = NrfDecoder::Empty;
+            for (pos, bit) in frame.encode_fixed(0).iter().enumerate() {
+                state = state.push_bit(config, &out, bit, 1.0, pos as u64);
+            }
+        }
+        std::iter::from_fn(|| out.pop())
+            .filter(|(frame, _)| frame.corrected.is_some())
+            .count()
+    };
+
+    // each bit of payload and checksum has its own syndrome, matched by about one in 240 random
+    // checksums after 32 bytes of payload
+    let config = NrfConfig::fixed_length(39, 5, 32, &address).error_correction(true);
+    assert!(received(&config, 34) < 10);
+    let config = NrfConfig::fixed_length(39, 5, 8, &address).error_correction(true);
+    assert!(received(&config, 10) < 10);
+    let config = config.crc_length(1);
+    assert_eq!(received(&config, 9), 0);
+}
+
+#[test]
+fn test_crc_errors() {
+    let packet = NrfFrame {
+        address: vec![1, 2, 3, 4],
+        payload: vec![5, 6, 7, 8],
+        ..Default::default()
+    };
+    let mut bits = packet.encode_fixed(2);
+    // first payload bit, following preamble and address
+    bits.set(8 + 32, !bits[8 + 32]);
+    let receive = |config: &NrfConfig| {
+        let out = NoCopyStream::newp();
+        let mut state = NrfDecoder::Empty;
+        for (pos, bit) in bits.iter().enumerate() {
+            state = state.push_bit(config, &out, bit, 1.0, pos as u64);
+        }
+        out.pop().map(|(frame, _)| frame)
+    };
+
+    let config = NrfConfig::fixed_length(39, 4, 4, &[1, 2, 3, 4]).report_crc_errors(true);
+    let frame = receive(&config).expect("Frame not reported");
+    assert!(!frame.crc_ok());
+    assert_eq!(frame.payload, vec![0x85, 6, 7, 8]);
+    assert_eq!(
+        frame.crc,
+        Some(CRC16.checksum(&[1, 2, 3, 4, 5, 6, 7, 8]) as u32)
+    );
+    assert_eq!(
+        frame.computed_crc,
+        Some(CRC16.checksum(&[1, 2, 3, 4, 0x85, 6, 7, 8]) as u32)
+    );
+    // corrected frames are not reported as errors
+    let frame = receive(&config.clone().error_correction(true)).expect("Correction failed");
+    assert!(frame.crc_ok());
+    assert_eq!(frame.payload, packet.payload);
+    // errors are not reported without the full address
+    let config = NrfConfig::fixed_length(39, 4, 4, &[1, 2]).report_crc_errors(true);
+    assert!(receive(&config).is_none());
+}
+
+#[test]
+fn test_soft_decision() {
+    let packet = NrfFrame {
+        address: vec![1, 2, 3, 4],
+        payload: vec![5, 6, 7, 8],
+        pcf: Some(Pcf {
+            length: 4,
+            pid: 2,
+            no_ack: false,
+        }),
+        ..Default::default()
+    };
+    // flips bits counted from the first address bit, following the 8-bit preamble, and receives
+    // them with low confidence along with further weak bits that are correct
+    let receive = |config: &NrfConfig, bits: BitVec, errors: &[usize], weak: &[usize]| {
+        let out = NoCopyStream::newp();
+        let mut state = NrfDecoder::Empty;
+        for (pos, bit) in bits.iter().enumerate() {
+            let (bit, confidence) = match pos.checked_sub(8) {
+                Some(p) if errors.contains(&p) => (!bit, 0.1),
+                Some(p) if weak.contains(&p) => (bit, 0.2),
+                _ => (bit, 1.0),
+            };
+            state = state.push_bit(config, &out, bit, confidence, pos as u64);
+        }
+        out.pop().map(|(frame, _)| frame)
+    };
+
+    let config = NrfConfig::fixed_length(39, 4, 4, &[1, 2, 3, 4]).soft_decision(true);
+    for errors in [vec![40], vec![33, 50, 71], vec![32, 45, 46, 60, 79]] {
+        let frame = receive(&config, packet.encode_fixed(2), &errors, &[35, 52, 70])
+            .expect("Correction failed");
+        assert_eq!(frame.payload, packet.payload);
+        assert_eq!(frame.corrected, Some(errors));
+    }
+    // errors are not corrected without the full address
+    let config = NrfConfig::fixed_length(39, 4, 4, &[1, 2]).soft_decision(true);
+    assert!(receive(&config, packet.encode_fixed(2), &[40, 50, 60], &[]).is_none());
+
+    // PID and payload of ESB frames, but not the payload length
+    let config = NrfConfig::shockburst(39, 4, None, &[1, 2, 3, 4]).soft_decision(true);
+    let errors = vec![38, 50, 61, 80];
+    let frame =
+        receive(&config, packet.encode_dynamic(2), &errors, &[39, 70]).expect("Correction failed");
+    assert_eq!(frame.pcf, packet.pcf);
+    assert_eq!(frame.payload, packet.payload);
+    assert_eq!(frame.corrected, Some(errors));
+    // errors are not corrected with a 1-byte CRC
+    let config = config.crc_length(1);
+    assert!(receive(&config, packet.encode_dynamic(1), &[46], &[]).is_none());
 }
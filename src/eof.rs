@@ -0,0 +1,146 @@
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStreamp, Streamp};
+use rustradio::Error;
+
+/// Streams whose end is passed on from block to block
+pub trait Ends {
+    /// Whether the stream has been ended and all of its items have been read
+    fn ended(&self) -> bool;
+
+    /// Marks the stream as ended, which takes effect once its remaining items are read
+    fn end(&self);
+}
+
+// rustradio checks for remaining items before reading the end mark, which may have been set after
+// the last items were written in between, so that the items are checked for once more afterwards
+impl<T: Copy> Ends for Streamp<T> {
+    fn ended(&self) -> bool {
+        self.eof() && self.read_buf().is_ok_and(|(items, _)| items.is_empty())
+    }
+
+    fn end(&self) {
+        self.set_eof();
+    }
+}
+
+// the end mark is read while the items are locked, so that none can be written in between
+impl<T> Ends for NoCopyStreamp<T> {
+    fn ended(&self) -> bool {
+        self.eof()
+    }
+
+    fn end(&self) {
+        self.set_eof();
+    }
+}
+
+// streams of blocks without inputs never end
+impl Ends for () {
+    fn ended(&self) -> bool {
+        false
+    }
+
+    fn end(&self) {}
+}
+
+impl<A: Ends, B: Ends> Ends for (A, B) {
+    fn ended(&self) -> bool {
+        self.0.ended() && self.1.ended()
+    }
+
+    fn end(&self) {
+        self.0.end();
+        self.1.end();
+    }
+}
+
+impl<T: Ends> Ends for Vec<T> {
+    fn ended(&self) -> bool {
+        self.iter().all(Ends::ended)
+    }
+
+    fn end(&self) {
+        self.iter().for_each(Ends::end);
+    }
+}
+
+/// Ends the outputs of a block if all of its inputs have ended, returns whether they have
+///
+/// Meant to be called by blocks without further input, which return EOF if so.
+pub fn propagate(inputs: &impl Ends, outputs: &impl Ends) -> bool {
+    let ended = inputs.ended();
+    if ended {
+        outputs.end();
+    }
+    ended
+}
+
+/// Block of rustradio whose outputs are ended once it has ended or its inputs have ended
+///
+/// Blocks of rustradio neither mark the streams of a source as ended at the end of a recording, nor
+/// pass on the end of their input streams, which is required to flush blocks holding back items.
+pub struct EndWithInputs<I, O> {
+    block: Box<dyn Block + Send>,
+    inputs: I,
+    outputs: O,
+}
+
+impl<I: Ends, O: Ends> EndWithInputs<I, O> {
+    pub fn new(block: impl Block + Send + 'static, inputs: I, outputs: O) -> Self {
+        Self {
+            block: Box::new(block),
+            inputs,
+            outputs,
+        }
+    }
+}
+
+impl<I: Ends, O: Ends> Block for EndWithInputs<I, O> {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        match self.block.work()? {
+            BlockRet::EOF => {
+                self.outputs.end();
+                Ok(BlockRet::EOF)
+            }
+            BlockRet::Noop if self.eof() => Ok(BlockRet::EOF),
+            ret => Ok(ret),
+        }
+    }
+}
+
+impl<I: Ends, O: Ends> BlockEOF for EndWithInputs<I, O> {
+    fn eof(&mut self) -> bool {
+        propagate(&self.inputs, &self.outputs)
+    }
+}
+
+impl<I, O> BlockName for EndWithInputs<I, O> {
+    fn block_name(&self) -> &str {
+        self.block.block_name()
+    }
+}
+
+#[test]
+fn test_end_with_inputs() {
+    use rustradio::blocks::{BinarySlicer, VectorSource};
+
+    let source = VectorSource::new(vec![1.0, -1.0]);
+    let samples = source.out();
+    let mut source = EndWithInputs::new(source, (), samples.clone());
+    let slicer = BinarySlicer::new(samples.clone());
+    let bits = slicer.out();
+    let mut slicer = EndWithInputs::new(slicer, samples.clone(), bits.clone());
+
+    assert_eq!(source.work().expect("Source failed"), BlockRet::Ok);
+    assert_eq!(source.work().expect("Source failed"), BlockRet::EOF);
+    // the end of the samples takes effect once they have been read
+    assert!(!samples.ended());
+    assert_eq!(slicer.work().expect("Slicing failed"), BlockRet::Ok);
+    assert!(samples.ended());
+    assert!(!bits.ended());
+    assert_eq!(slicer.work().expect("Slicing failed"), BlockRet::EOF);
+    let (out, _) = bits.read_buf().expect("Stream not readable");
+    assert_eq!(out.slice(), &[1, 0]);
+    out.consume(2);
+    assert!(bits.ended());
+}
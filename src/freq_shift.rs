@@ -0,0 +1,87 @@
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp};
+use rustradio::{Complex, Error};
+use std::f64::consts::PI;
+
+/// Shifts the frequency of a stream by multiplying it with a complex sine wave
+///
+/// Unlike a separate signal source, the sine wave ends with the input stream.
+pub struct FreqShift {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    rad_per_sample: f64,
+    // phase of the next sample
+    phase: f64,
+}
+
+impl FreqShift {
+    pub fn new(src: Streamp<Complex>, sample_rate: f32, freq: f32) -> Self {
+        Self {
+            src,
+            dst: Stream::newp(),
+            rad_per_sample: 2.0 * PI * freq as f64 / sample_rate as f64,
+            phase: 0.0,
+        }
+    }
+
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+}
+
+impl Block for FreqShift {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        if input.is_empty() {
+            drop(input);
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::OutputFull);
+        }
+        for (w, s) in o.slice().iter_mut().zip(input.iter()).take(n) {
+            let (sin, cos) = self.phase.sin_cos();
+            *w = s * Complex::new(cos as f32, sin as f32);
+            self.phase = (self.phase + self.rad_per_sample) % (2.0 * PI);
+        }
+        input.consume(n);
+        o.produce(n, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for FreqShift {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for FreqShift {
+    fn block_name(&self) -> &str {
+        "FreqShift"
+    }
+}
+
+#[test]
+fn test_freq_shift() {
+    use crate::test_util::stream_of;
+
+    let src = stream_of(&[Complex::new(1.0, 0.0); 4]);
+
+    // quarter turn per sample
+    let mut shift = FreqShift::new(src, 4.0, 1.0);
+    shift.work().expect("Shifting failed");
+    let out = shift.out();
+    let (out, _) = out.read_buf().expect("Stream not readable");
+    let expected = [(1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0)];
+    for (s, (re, im)) in out.iter().zip(expected) {
+        assert!((s - Complex::new(re, im)).norm() < 1e-6);
+    }
+}
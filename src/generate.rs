@@ -0,0 +1,272 @@
+use crate::deframer::{channel_freq, NrfFrame, Pcf};
+use crate::modulator::Modulator;
+use crate::sigmf::{self, Meta};
+use crate::sigmf_annotator::annotation;
+use crate::{occupied_bandwidth, range_validator, DataRate};
+use anyhow::{anyhow, Result};
+use rustradio::{Complex, Error};
+use std::io::Write;
+use structopt::StructOpt;
+
+// amplitude of the generated signal, leaving headroom for noise and multipath
+const AMPLITUDE: f32 = 0.5;
+
+/// Options of the generate subcommand
+#[derive(StructOpt, Debug, Clone)]
+pub struct GenerateOpt {
+    /// Writes complex float samples to file, or to SigMF recording with annotations of all packets
+    #[structopt(short = "o", long = "output")]
+    pub output: String,
+
+    /// Channels the packets are sent on in turn, must be within range [0,125]
+    #[structopt(short = "c", long = "channel", min_values = 1, required = true, validator = range_validator(0, 125), use_delimiter = true)]
+    pub channels: Vec<u8>,
+
+    /// Sample rate in MHz
+    #[structopt(short = "s", long = "sample", default_value = "4")]
+    pub sample_rate_mhz: f32,
+
+    /// Center frequency in MHz, derived from selected channels if omitted
+    #[structopt(short = "f", long = "freq")]
+    pub center_freq_mhz: Option<f32>,
+
+    /// Data rate (250kbps, 1Mpbs or 2Mpbs)
+    #[structopt(short="r", long = "rate", default_value = "1", possible_values = &["250k", "1", "2"])]
+    pub rate: DataRate,
+
+    /// Hexadecimal address of 3 to 5 bytes
+    #[structopt(short = "p", long = "address")]
+    pub address: String,
+
+    /// Hexadecimal payloads of the packets, repeated if there are more packets than payloads
+    #[structopt(
+        long = "payload",
+        min_values = 1,
+        required = true,
+        use_delimiter = true
+    )]
+    pub payloads: Vec<String>,
+
+    /// Sends Enhanced ShockBurst (ESB) packets with incrementing packet ID
+    #[structopt(short = "e", long = "shockburst")]
+    pub shockburst: bool,
+
+    /// CRC length in bytes
+    #[structopt(long = "crc", default_value = "2", possible_values = &["0", "1", "2"])]
+    pub crc_length: usize,
+
+    /// Number of packets
+    #[structopt(short = "n", long = "count", default_value = "10")]
+    pub count: usize,
+
+    /// Time in milliseconds from the start of a packet to the start of the next one
+    #[structopt(long = "interval", default_value = "1")]
+    pub interval_ms: f64,
+
+    /// Adds white noise at the given signal-to-noise ratio in dB, measured within the bandwidth of the data rate
+    #[structopt(long = "snr")]
+    pub snr: Option<f64>,
+
+    /// Carrier frequency offset in Hz
+    #[structopt(long = "freq-offset", default_value = "0", allow_hyphen_values = true)]
+    pub freq_offset: f64,
+
+    /// Symbol rate error in parts per million
+    #[structopt(long = "drift", default_value = "0", allow_hyphen_values = true)]
+    pub drift_ppm: f64,
+
+    /// Adds an echo of the signal delayed by the given time in nanoseconds, rounded to whole samples
+    #[structopt(long = "multipath-delay")]
+    pub multipath_delay_ns: Option<f64>,
+
+    /// Amplitude of the echo relative to the signal
+    #[structopt(long = "multipath-gain", default_value = "0.5")]
+    pub multipath_gain: f32,
+
+    /// Seed of the noise generator
+    #[structopt(long = "seed", default_value = "1")]
+    pub seed: u64,
+}
+
+impl GenerateOpt {
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate_mhz as f64 * 1_000_000.0
+    }
+
+    pub fn center_freq(&self) -> f64 {
+        let min = *self.channels.iter().min().unwrap();
+        let max = *self.channels.iter().max().unwrap();
+        self.center_freq_mhz
+            .map_or((channel_freq(min) + channel_freq(max)) / 2.0, |f| {
+                f as f64 * 1_000_000.0
+            })
+    }
+
+    /// Samples of all packets with the given impairments, and the packets at their positions
+    /// within the samples
+    pub fn signal(&self) -> std::result::Result<(Vec<Complex>, Vec<NrfFrame>), Error> {
+        let sample_rate = self.sample_rate();
+        let bit_rate = self.rate.bits_per_second() as f64;
+        let modulator = Modulator::new(sample_rate, bit_rate, self.rate.deviation() as f64)
+            .drift(self.drift_ppm);
+        let interval = (self.interval_ms / 1000.0 * sample_rate) as usize;
+        let mut samples = vec![Complex::default(); interval * self.count];
+
+        let mut frames = self.frames()?;
+        for (i, frame) in frames.iter_mut().enumerate() {
+            let bits: Vec<bool> = match frame.pcf {
+                Some(_) => frame.encode_dynamic(self.crc_length),
+                None => frame.encode_fixed(self.crc_length),
+            }
+            .iter()
+            .collect();
+            let freq = channel_freq(frame.channel) - self.center_freq() + self.freq_offset;
+            let signal = modulator.modulate(&bits, freq);
+            if signal.len() > interval {
+                return Err(Error::new("Interval too short for packets"));
+            }
+            // packets are centered within their interval
+            let start = i * interval + (interval - signal.len()) / 2;
+            for (s, x) in samples[start..].iter_mut().zip(signal) {
+                *s = x * AMPLITUDE;
+            }
+            frame.sample_start = start as u64;
+            frame.sample_count = (bits.len() as f64 * modulator.samples_per_symbol()) as u64;
+        }
+
+        if let Some(delay_ns) = self.multipath_delay_ns {
+            let delay = (delay_ns / 1e9 * sample_rate).round() as usize;
+            for n in (delay..samples.len()).rev() {
+                let echo = samples[n - delay] * self.multipath_gain;
+                samples[n] += echo;
+            }
+        }
+
+        if let Some(snr) = self.snr {
+            // noise power within the bandwidth of the data rate relative to the signal power
+            let power =
+                (AMPLITUDE * AMPLITUDE) as f64 / 10f64.powf(snr / 10.0) * sample_rate / bit_rate;
+            let mut noise = Noise(self.seed.max(1));
+            for s in samples.iter_mut() {
+                *s += noise.sample((power / 2.0).sqrt());
+            }
+        }
+        Ok((samples, frames))
+    }
+
+    // frames to send, without position within the sample stream
+    fn frames(&self) -> Result<Vec<NrfFrame>> {
+        let address = hex::decode(&self.address)?;
+        if !(3..=5).contains(&address.len()) {
+            return Err(anyhow!("Address must be 3 to 5 bytes long"));
+        }
+        let payloads = self
+            .payloads
+            .iter()
+            .map(hex::decode)
+            .collect::<Result<Vec<_>, _>>()?;
+        if payloads.iter().any(|p| p.len() > 32) {
+            return Err(anyhow!("Payload exceeds 32 bytes"));
+        }
+        Ok((0..self.count)
+            .map(|i| {
+                let payload = payloads[i % payloads.len()].clone();
+                NrfFrame {
+                    channel: self.channels[i % self.channels.len()],
+                    address: address.clone(),
+                    pcf: self.shockburst.then_some(Pcf {
+                        length: payload.len() as u8,
+                        pid: (i % 4) as u8,
+                        no_ack: false,
+                    }),
+                    payload,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+}
+
+// pseudo-random generator of normally distributed values (xorshift64* and Box-Muller transform)
+struct Noise(u64);
+
+impl Noise {
+    fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let x = self.0.wrapping_mul(0x2545f4914f6cdd1d);
+        // in (0, 1] to allow taking the logarithm
+        ((x >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    // complex sample with the given standard deviation of each component
+    fn sample(&mut self, std_dev: f64) -> Complex {
+        let r = (-2.0 * self.uniform().ln()).sqrt() * std_dev;
+        let theta = 2.0 * std::f64::consts::PI * self.uniform();
+        Complex::new((r * theta.cos()) as f32, (r * theta.sin()) as f32)
+    }
+}
+
+/// Writes IQ samples of nRF packets with the given impairments
+pub fn run(opt: &GenerateOpt) -> std::result::Result<(), Error> {
+    let (samples, frames) = opt.signal()?;
+    let base = sigmf::base_name(&opt.output);
+    let data_path = base.map_or(opt.output.clone(), sigmf::data_path);
+    let mut w = std::io::BufWriter::new(std::fs::File::create(data_path)?);
+    for s in &samples {
+        w.write_all(&s.re.to_le_bytes())?;
+        w.write_all(&s.im.to_le_bytes())?;
+    }
+    w.flush()?;
+
+    if let Some(base) = base {
+        let mut meta = Meta::recording(opt.sample_rate(), opt.center_freq(), None);
+        meta.global.recorder = Some(format!("{} generate", env!("CARGO_PKG_NAME")));
+        meta.annotations = frames
+            .iter()
+            .map(|frame| annotation(frame, occupied_bandwidth(opt.rate, opt.rate.deviation())))
+            .collect();
+        meta.save(base)?;
+    }
+    eprintln!(
+        "Generated {} packets in {} samples",
+        frames.len(),
+        samples.len()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generate_opt() {
+    let opt = GenerateOpt::from_iter_safe([
+        "generate",
+        "-o",
+        "out.cf32",
+        "-c",
+        "10,20",
+        "-p",
+        "a1b2c3",
+        "--payload",
+        "01,0203",
+        "-e",
+        "-n",
+        "5",
+    ])
+    .expect("Parsing failed");
+    assert_eq!(opt.center_freq(), 2_415_000_000.0);
+    let frames = opt.frames().expect("Invalid frames");
+    assert_eq!(frames.len(), 5);
+    assert_eq!(
+        frames.iter().map(|f| f.channel).collect::<Vec<_>>(),
+        [10, 20, 10, 20, 10]
+    );
+    assert_eq!(frames[3].payload, vec![2, 3]);
+    assert_eq!(frames[3].pcf.map(|pcf| (pcf.length, pcf.pid)), Some((2, 3)));
+    assert_eq!(frames[4].pcf.map(|pcf| pcf.pid), Some(0));
+
+    let mut noise = Noise(1);
+    let n = 100_000;
+    let power: f32 = (0..n).map(|_| noise.sample(0.5).norm_sqr()).sum::<f32>() / n as f32;
+    assert!((power - 0.5).abs() < 0.01);
+}
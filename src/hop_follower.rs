@@ -0,0 +1,268 @@
+use crate::deframer::NrfFrame;
+use crate::eof;
+use crate::sweep_source::{Position, Tuning};
+use itertools::Itertools;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp};
+use rustradio::Error;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// minimum time without frames until the device is considered to have hopped away
+const LOST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Hop sequence and timing of a device, learned from the channels of its frames
+#[derive(Debug, Default)]
+pub struct HopModel {
+    // number of hops observed from one channel to another
+    transitions: BTreeMap<(u8, u8), usize>,
+    // channel of the latest frame and the position of the first frame on it
+    current: Option<(u8, u64)>,
+    // accumulated time spent on channels until hopping away, in samples
+    dwell_sum: u64,
+    dwell_count: u64,
+}
+
+impl HopModel {
+    // adds a frame received on a channel at a position within the sample stream
+    fn observe(&mut self, channel: u8, pos: u64) {
+        match self.current {
+            Some((prev, _)) if prev == channel => {}
+            Some((prev, start)) => {
+                *self.transitions.entry((prev, channel)).or_default() += 1;
+                self.dwell_sum += pos.saturating_sub(start);
+                self.dwell_count += 1;
+                self.current = Some((channel, pos));
+            }
+            None => self.current = Some((channel, pos)),
+        }
+    }
+
+    // channel most often hopped to from the given one
+    fn predict(&self, channel: u8) -> Option<u8> {
+        self.transitions
+            .iter()
+            .filter(|((from, _), _)| *from == channel)
+            .max_by_key(|(_, count)| **count)
+            .map(|((_, to), _)| *to)
+    }
+
+    // mean time spent on a channel until hopping away, in samples
+    fn mean_dwell(&self) -> Option<u64> {
+        (self.dwell_count > 0).then(|| self.dwell_sum / self.dwell_count)
+    }
+}
+
+impl fmt::Display for HopModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.transitions.is_empty() {
+            return write!(f, "no hops observed");
+        }
+        write!(
+            f,
+            "{}",
+            self.transitions
+                .iter()
+                .map(|((from, to), count)| format!("{} -> {} ({})", from, to, count))
+                .join(", ")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    // sweeping until the device is received
+    Searching,
+    // holding on the channel of the latest frame, received at the given sample position
+    Following(u64),
+    // holding on the channel predicted after the device was lost at the given sample position
+    Predicting(u64),
+}
+
+/// Follows a frequency hopping device by holding a sweep on the channels it is expected on
+///
+/// While frames are received, the sweep is held on a frequency covering the current channel
+/// and, if possible, the channel predicted to follow. Once the device is lost, the sweep is
+/// moved to the predicted channel, and resumes if the device is not found there either. Only
+/// frames of the followed addresses are tracked, frames of other devices are passed on unchanged.
+pub struct HopFollower {
+    src: NoCopyStreamp<NrfFrame>,
+    dst: NoCopyStreamp<NrfFrame>,
+    tuning: Arc<Mutex<Tuning>>,
+    // prefixes of the addresses of the followed device
+    addresses: Vec<Vec<u8>>,
+    // number of samples received so far, frames are decoded behind it
+    position: Position,
+    // number of channels covered by a frequency above its lowest one
+    width: u8,
+    sample_rate: f32,
+    model: Arc<Mutex<HopModel>>,
+    state: State,
+}
+
+impl HopFollower {
+    pub fn new(
+        src: NoCopyStreamp<NrfFrame>,
+        tuning: Arc<Mutex<Tuning>>,
+        addresses: Vec<Vec<u8>>,
+        position: Position,
+        width: u8,
+        sample_rate: f32,
+        model: Arc<Mutex<HopModel>>,
+    ) -> Self {
+        Self {
+            src,
+            dst: NoCopyStream::newp(),
+            tuning,
+            addresses,
+            position,
+            width,
+            sample_rate,
+            model,
+            state: State::Searching,
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+
+    // lowest channel of a frequency covering the channel, and the next one if both fit
+    fn base(&self, channel: u8, next: Option<u8>) -> u8 {
+        match next {
+            Some(next) if channel.abs_diff(next) <= self.width => {
+                let margin = self.width - channel.abs_diff(next);
+                channel.min(next).saturating_sub(margin / 2)
+            }
+            _ => channel.saturating_sub(self.width / 2),
+        }
+    }
+
+    // number of samples without frames after which the device is considered lost
+    fn timeout(&self, model: &HopModel) -> u64 {
+        let min = (LOST_TIMEOUT.as_secs_f32() * self.sample_rate) as u64;
+        model.mean_dwell().map_or(min, |dwell| (2 * dwell).max(min))
+    }
+}
+
+impl Block for HopFollower {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (model, tuning) = (self.model.clone(), self.tuning.clone());
+        let mut model = model.lock().unwrap();
+        let mut tuning = tuning.lock().unwrap();
+        let timeout = self.timeout(&model);
+        let position = *self.position.lock().unwrap();
+        let current = model.current.map(|(channel, _)| channel);
+        match (self.state, current) {
+            (State::Following(since), Some(channel)) if position > since + timeout => {
+                self.state = match model.predict(channel) {
+                    Some(next) => {
+                        *tuning = Tuning::Hold(self.base(next, None));
+                        State::Predicting(position)
+                    }
+                    None => {
+                        *tuning = Tuning::Sweep;
+                        State::Searching
+                    }
+                };
+            }
+            (State::Predicting(since), _) if position > since + timeout => {
+                *tuning = Tuning::Sweep;
+                self.state = State::Searching;
+            }
+            _ => {}
+        }
+
+        let (v, tags) = match self.src.pop() {
+            None if self.eof() => return Ok(BlockRet::EOF),
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+        if !self.addresses.iter().any(|a| v.address.starts_with(a)) {
+            self.dst.push(v, &tags);
+            return Ok(BlockRet::Ok);
+        }
+        model.observe(v.channel, v.sample_start);
+        *tuning = Tuning::Hold(self.base(v.channel, model.predict(v.channel)));
+        self.state = State::Following(v.sample_start);
+        self.dst.push(v, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for HopFollower {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for HopFollower {
+    fn block_name(&self) -> &str {
+        "HopFollower"
+    }
+}
+
+#[test]
+fn test_hop_model() {
+    let mut model = HopModel::default();
+    for (channel, pos) in [
+        (10, 0),
+        (10, 50),
+        (40, 100),
+        (70, 300),
+        (10, 400),
+        (40, 500),
+    ] {
+        model.observe(channel, pos);
+    }
+    assert_eq!(model.predict(10), Some(40));
+    assert_eq!(model.predict(40), Some(70));
+    assert_eq!(model.predict(5), None);
+    // stays of 100, 200, 100 and 100 samples
+    assert_eq!(model.mean_dwell(), Some(125));
+    assert_eq!(
+        model.to_string(),
+        "10 -> 40 (2), 40 -> 70 (1), 70 -> 10 (1)"
+    );
+
+    let src = NoCopyStream::newp();
+    let tuning = Arc::new(Mutex::new(Tuning::Sweep));
+    let position = Position::default();
+    let mut follower = HopFollower::new(
+        src.clone(),
+        tuning.clone(),
+        vec![vec![0xe7, 0xe7]],
+        position.clone(),
+        8,
+        1e6,
+        Default::default(),
+    );
+    let frame = |channel, address: &[u8]| NrfFrame {
+        channel,
+        address: address.to_vec(),
+        sample_start: 1000,
+        ..Default::default()
+    };
+    // frames of other devices do not affect the sweep
+    src.push(frame(20, &[0xe8, 0xe7, 0xe7]), &[]);
+    *position.lock().unwrap() = 2000;
+    follower.work().expect("Following failed");
+    assert_eq!(*tuning.lock().unwrap(), Tuning::Sweep);
+    assert!(follower.out().pop().is_some());
+    src.push(frame(40, &[0xe7, 0xe7, 0xe7]), &[]);
+    follower.work().expect("Following failed");
+    assert_eq!(*tuning.lock().unwrap(), Tuning::Hold(36));
+    // hold on both channels once the hop is known
+    assert_eq!(follower.base(40, Some(45)), 39);
+    assert_eq!(follower.base(3, Some(1)), 0);
+    // the sweep resumes once no frames have been received for the timeout in samples, as no
+    // hops are known to predict the next channel
+    *position.lock().unwrap() = 500_000;
+    follower.work().expect("Following failed");
+    assert_eq!(*tuning.lock().unwrap(), Tuning::Hold(36));
+    *position.lock().unwrap() = 501_001;
+    follower.work().expect("Following failed");
+    assert_eq!(*tuning.lock().unwrap(), Tuning::Sweep);
+}
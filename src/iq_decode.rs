@@ -0,0 +1,198 @@
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp};
+use rustradio::{Complex, Error, Float};
+use std::str::FromStr;
+
+/// Sample format of recorded IQ files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+    // interleaved 32-bit little-endian floats
+    Cf32,
+    // interleaved signed 8-bit integers (HackRF native)
+    Cs8,
+    // interleaved signed 16-bit little-endian integers
+    Cs16,
+}
+
+impl SampleFormat {
+    // size of a single complex sample in bytes
+    pub fn sample_size(&self) -> usize {
+        match self {
+            SampleFormat::Cf32 => 8,
+            SampleFormat::Cs8 => 2,
+            SampleFormat::Cs16 => 4,
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> Complex {
+        match self {
+            SampleFormat::Cf32 => Complex::new(
+                Float::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                Float::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            ),
+            SampleFormat::Cs8 => Complex::new(
+                data[0] as i8 as Float / 128.0,
+                data[1] as i8 as Float / 128.0,
+            ),
+            SampleFormat::Cs16 => Complex::new(
+                i16::from_le_bytes([data[0], data[1]]) as Float / 32768.0,
+                i16::from_le_bytes([data[2], data[3]]) as Float / 32768.0,
+            ),
+        }
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cf32" => Ok(SampleFormat::Cf32),
+            "cs8" => Ok(SampleFormat::Cs8),
+            "cs16" => Ok(SampleFormat::Cs16),
+            _ => Err(format!("Unknown sample format: {}", s)),
+        }
+    }
+}
+
+/// Decode raw bytes of interleaved IQ samples into complex samples
+///
+/// An incomplete sample at the end of the input is dropped.
+pub struct IqDecode {
+    src: Streamp<u8>,
+    dst: Streamp<Complex>,
+    format: SampleFormat,
+    // bytes of a sample which has not been read completely yet
+    partial: Vec<u8>,
+}
+
+impl IqDecode {
+    pub fn new(src: Streamp<u8>, format: SampleFormat) -> Self {
+        Self {
+            src,
+            dst: Stream::newp(),
+            format,
+            partial: Vec::with_capacity(format.sample_size()),
+        }
+    }
+
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+}
+
+impl Block for IqDecode {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, _tags) = self.src.read_buf()?;
+        if input.is_empty() {
+            drop(input);
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        let mut o = self.dst.write_buf()?;
+        if o.is_empty() {
+            return Ok(BlockRet::OutputFull);
+        }
+        let size = self.format.sample_size();
+        let mut data = input.slice();
+        let mut produced = 0;
+        if !self.partial.is_empty() {
+            let missing = std::cmp::min(size - self.partial.len(), data.len());
+            self.partial.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+            if self.partial.len() == size {
+                o.slice()[0] = self.format.decode(&self.partial);
+                self.partial.clear();
+                produced = 1;
+            }
+        }
+        let n = std::cmp::min(data.len() / size, o.len() - produced);
+        for (w, chunk) in o.slice()[produced..]
+            .iter_mut()
+            .zip(data.chunks_exact(size).take(n))
+        {
+            *w = self.format.decode(chunk);
+        }
+        data = &data[n * size..];
+        // the remaining bytes are kept, so that the input ends even if they never make a sample
+        if data.len() < size {
+            self.partial.extend_from_slice(data);
+            data = &[];
+        }
+        let consumed = input.len() - data.len();
+        input.consume(consumed);
+        o.produce(produced + n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for IqDecode {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for IqDecode {
+    fn block_name(&self) -> &str {
+        "IqDecode"
+    }
+}
+
+#[test]
+fn test_decode() {
+    let mut src = rustradio::blocks::VectorSource::new(vec![0x80, 0x40, 0x00, 0x80, 0xff, 0x7f]);
+    src.work().expect("Source failed");
+    let mut decode = IqDecode::new(src.out(), SampleFormat::Cs8);
+    decode.work().expect("Decoding failed");
+    let out = decode.out();
+    let (samples, _) = out.read_buf().expect("Reading output failed");
+    assert_eq!(samples.len(), 3);
+    assert_eq!(samples.slice()[0], Complex::new(-1.0, 0.5));
+    assert_eq!(samples.slice()[1], Complex::new(0.0, -1.0));
+    assert_eq!(
+        samples.slice()[2],
+        Complex::new(-1.0 / 128.0, 127.0 / 128.0)
+    );
+
+    let mut src = rustradio::blocks::VectorSource::new(vec![0x00, 0x80, 0xff, 0x7f]);
+    src.work().expect("Source failed");
+    let mut decode = IqDecode::new(src.out(), SampleFormat::Cs16);
+    decode.work().expect("Decoding failed");
+    let out = decode.out();
+    let (samples, _) = out.read_buf().expect("Reading output failed");
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples.slice()[0], Complex::new(-1.0, 32767.0 / 32768.0));
+}
+
+#[test]
+fn test_partial_sample() {
+    use crate::eof::Ends;
+
+    let src = Stream::newp();
+    let write = |bytes: &[u8]| {
+        let mut buf = src.write_buf().expect("Stream not writable");
+        buf.fill_from_slice(bytes);
+        buf.produce(bytes.len(), &[]);
+    };
+    let mut decode = IqDecode::new(src.clone(), SampleFormat::Cs8);
+    let out = decode.out();
+    // samples split across reads are decoded once complete
+    write(&[0x80, 0x40, 0x00]);
+    assert_eq!(decode.work().expect("Decoding failed"), BlockRet::Ok);
+    write(&[0x80, 0xff]);
+    src.end();
+    assert_eq!(decode.work().expect("Decoding failed"), BlockRet::Ok);
+    // the incomplete sample at the end is dropped
+    assert_eq!(decode.work().expect("Decoding failed"), BlockRet::EOF);
+    let (samples, _) = out.read_buf().expect("Reading output failed");
+    assert_eq!(
+        samples.slice(),
+        &[Complex::new(-1.0, 0.5), Complex::new(0.0, -1.0)]
+    );
+    samples.consume(2);
+    assert!(out.ended());
+}
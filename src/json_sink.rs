@@ -0,0 +1,103 @@
+use crate::deframer::{channel_freq, NrfFrame};
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::NoCopyStreamp;
+use rustradio::Error;
+use serde_json::json;
+use std::time::UNIX_EPOCH;
+
+/// Prints each frame as a single line JSON object
+pub struct JsonSink {
+    src: NoCopyStreamp<NrfFrame>,
+}
+
+impl JsonSink {
+    pub fn new(src: NoCopyStreamp<NrfFrame>) -> Self {
+        Self { src }
+    }
+}
+
+fn to_json(frame: &NrfFrame) -> serde_json::Value {
+    json!({
+        "channel": frame.channel,
+        "frequency": channel_freq(frame.channel),
+        "address": hex::encode(&frame.address),
+        "payload": hex::encode(&frame.payload),
+        "payload_length": frame.payload.len(),
+        "pid": frame.pcf.map(|pcf| pcf.pid),
+        "no_ack": frame.pcf.map(|pcf| pcf.no_ack),
+        "crc": frame.crc,
+        "crc_ok": frame.crc_ok(),
+        "computed_crc": frame.computed_crc,
+        "timestamp": frame.timestamp.map(|t| {
+            t.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64()
+        }),
+        "sample_offset": frame.sample_start,
+        "copies": frame.duplicates.as_ref().map(|d| d.count),
+        "channels": frame.duplicates.as_ref().map(|d| &d.channels),
+        "power": frame.signal.map(|s| s.power),
+        "snr": frame.signal.map(|s| s.snr),
+        "freq_offset": frame.signal.map(|s| s.freq_offset),
+        "ble": frame.ble.map(|ble| json!({
+            "channel_index": ble.channel_index,
+            "pdu_type": ble.pdu_type_name(),
+            "tx_add": ble.tx_add,
+            "rx_add": ble.rx_add,
+        })),
+        "corrected": frame.corrected,
+        "framing": frame.framing.map(|framing| json!({
+            "address_length": framing.address_len,
+            "shockburst": framing.shockburst,
+        })),
+    })
+}
+
+impl Block for JsonSink {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (v, _tags) = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+        println!("{}", to_json(&v));
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for JsonSink {}
+
+impl BlockName for JsonSink {
+    fn block_name(&self) -> &str {
+        "JsonSink"
+    }
+}
+
+#[test]
+fn test_json() {
+    let frame = NrfFrame {
+        channel: 39,
+        address: vec![0x07, 0x07, 0x00],
+        payload: vec![0xcb, 0x41],
+        crc: Some(0x1234),
+        sample_start: 100,
+        timestamp: Some(UNIX_EPOCH + std::time::Duration::from_millis(1500)),
+        signal: Some(crate::deframer::Signal {
+            power: -20.5,
+            snr: 15.0,
+            freq_offset: 12_000.0,
+        }),
+        ..Default::default()
+    };
+    let json = to_json(&frame);
+    assert_eq!(json["frequency"], 2_439_000_000.0);
+    assert_eq!(json["address"], "070700");
+    assert_eq!(json["payload"], "cb41");
+    assert_eq!(json["payload_length"], 2);
+    assert_eq!(json["pid"], serde_json::Value::Null);
+    assert_eq!(json["crc"], 0x1234);
+    assert_eq!(json["crc_ok"], true);
+    assert_eq!(json["timestamp"], 1.5);
+    assert_eq!(json["sample_offset"], 100);
+    assert_eq!(json["power"], -20.5);
+    assert_eq!(json["freq_offset"], 12_000.0);
+}
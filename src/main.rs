@@ -1,20 +1,71 @@
+mod benchmark;
+mod ble_deframer;
+mod channelizer;
+mod dedup;
 mod deframer;
-mod mul;
+mod eof;
+mod freq_shift;
+mod generate;
+mod hop_follower;
+mod iq_decode;
+mod json_sink;
+mod modulator;
+mod nocopy_tee;
 mod nrf_stat_sink;
+mod pcap_sink;
+mod preamble_correlator;
+mod promiscuous_deframer;
+mod sample_counter;
+mod scan_tracker;
+mod sigmf;
+mod sigmf_annotator;
+mod signal_probe;
 mod stdout_sink;
+mod sweep_source;
+mod symbol_sync;
+mod targets;
+#[cfg(test)]
+mod test_util;
 mod union;
 
-use deframer::{NrfConfig, NrfDeframer, NrfFrame};
-use mul::Multiply;
-use nrf_stat_sink::NrfStatSink;
-use rustradio::{graph::GraphRunner, stream::Streamp, Error};
+use benchmark::BenchmarkOpt;
+use bit_vec::BitVec;
+use ble_deframer::BleDeframer;
+use channelizer::Channelizer;
+use dedup::Dedup;
+use deframer::{channel_freq, NrfConfig, NrfDeframer, NrfFrame, StreamTiming};
+use eof::EndWithInputs;
+use freq_shift::FreqShift;
+use generate::GenerateOpt;
+use hop_follower::{HopFollower, HopModel};
+use iq_decode::{IqDecode, SampleFormat};
+use itertools::Itertools;
+use json_sink::JsonSink;
+use nocopy_tee::NoCopyTee;
+use nrf_stat_sink::{NoiseModel, NrfStatSink, Ranking};
+use pcap_sink::PcapSink;
+use preamble_correlator::{sync_patterns, PreambleCorrelator};
+use promiscuous_deframer::{PromiscuousDeframer, WINDOW_BITS};
+use rustradio::{file_sink::Mode, graph::GraphRunner, stream::Streamp, Error};
 use rustradio::{mtgraph::MTGraph, stream::NoCopyStreamp, window::WindowType, Complex};
+use sample_counter::SampleCounter;
+use scan_tracker::ScanTracker;
+use sigmf::Meta;
+use sigmf_annotator::SigMFAnnotator;
+use signal_probe::SignalProbe;
+use std::collections::BTreeMap;
 use std::iter::{self, zip};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use stdout_sink::StdoutSink;
 use structopt::{
-    clap::{crate_authors, crate_description, crate_name},
+    clap::{crate_authors, crate_description, crate_name, AppSettings},
     StructOpt,
 };
+use sweep_source::{Position, Schedule, SweepSource, Tuning};
+use symbol_sync::SymbolSync;
+use targets::Target;
 
 use rustradio::blocks::*;
 use union::Union;
@@ -28,6 +79,17 @@ macro_rules! add_block {
     }};
 }
 
+// adds a block of rustradio, whose outputs are ended once the given inputs have ended
+macro_rules! add_ending_block {
+    ($g:ident, $inputs:expr, $cons:expr) => {{
+        let inputs = $inputs;
+        let block = $cons;
+        let prev = block.out();
+        $g.add(Box::new(EndWithInputs::new(block, inputs, prev.clone())));
+        prev
+    }};
+}
+
 fn range_validator(min: u8, max: u8) -> impl Fn(std::string::String) -> Result<(), String> {
     move |value: String| {
         if (min..=max).map(|x| x.to_string()).any(|x| x == value) {
@@ -39,183 +101,858 @@ fn range_validator(min: u8, max: u8) -> impl Fn(std::string::String) -> Result<(
     }
 }
 
+// address prefixes are given in hex and may not exceed the longest address of 5 bytes
+fn address_validator(value: String) -> Result<(), String> {
+    match hex::decode(&value) {
+        Ok(address) if address.len() <= 5 => Ok(()),
+        Ok(_) => Err("Must not exceed the address length of at most 5 bytes".to_string()),
+        Err(err) => Err(format!("Must be hexadecimal: {}", err)),
+    }
+}
+
+// channel to receive on
+#[derive(Debug, Clone, Copy)]
+enum Channel {
+    // nRF channel (0-125) using ShockBurst or Enhanced ShockBurst framing
+    Nrf(u8),
+    // BLE advertising channel index (37-39)
+    Ble(u8),
+}
+
+impl Channel {
+    // RF channel, i.e. offset of center frequency from 2400 MHz in MHz
+    fn rf_channel(&self) -> u8 {
+        match self {
+            Channel::Nrf(channel) => *channel,
+            Channel::Ble(index) => ble_deframer::rf_channel(*index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DataRate {
+    Kbps250,
+    Mbps1,
+    Mbps2,
+}
+
+impl DataRate {
+    fn bits_per_second(&self) -> f32 {
+        match self {
+            DataRate::Kbps250 => 250_000.0,
+            DataRate::Mbps1 => 1_000_000.0,
+            DataRate::Mbps2 => 2_000_000.0,
+        }
+    }
+
+    fn kbps(&self) -> u16 {
+        (self.bits_per_second() / 1000.0) as u16
+    }
+
+    // nominal frequency deviation of the GFSK modulation
+    fn deviation(&self) -> f32 {
+        match self {
+            DataRate::Kbps250 | DataRate::Mbps1 => 160_000.0,
+            DataRate::Mbps2 => 320_000.0,
+        }
+    }
+
+    // minimum distance of channels in MHz to avoid overlapping signals
+    fn channel_spacing(&self) -> u8 {
+        match self {
+            DataRate::Kbps250 | DataRate::Mbps1 => 1,
+            DataRate::Mbps2 => 2,
+        }
+    }
+}
+
+// nominal frequency deviation of BLE, modulation index of 0.5
+const BLE_DEVIATION: f32 = 250_000.0;
+
+// sample rate while scanning unless set explicitly
+const SCAN_SAMPLE_RATE_MHZ: f32 = 10.0;
+
+// demodulated samples per symbol targeted by decimation after channel filter
+const MIN_SAMPLES_PER_SYMBOL: f32 = 4.0;
+
+// occupied bandwidth of a GFSK signal according to Carson's rule
+fn occupied_bandwidth(rate: DataRate, deviation: f32) -> f32 {
+    2.0 * deviation + rate.bits_per_second()
+}
+
+// cut-off frequency and transition width of a channel filter passing the occupied bandwidth
+fn channel_filter(rate: DataRate, deviation: f32) -> (f32, f32) {
+    (
+        occupied_bandwidth(rate, deviation) / 2.0,
+        rate.bits_per_second() / 4.0,
+    )
+}
+
+impl FromStr for DataRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "250k" => Ok(DataRate::Kbps250),
+            "1" => Ok(DataRate::Mbps1),
+            "2" => Ok(DataRate::Mbps2),
+            _ => Err(format!("Unknown data rate: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum OutputFormat {
+    // fixed-width columns of hexadecimal address and payload
+    Text,
+    // one JSON object per line
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
 #[derive(StructOpt, Debug)]
-#[structopt(author = crate_authors!(), name = crate_name!(), about = crate_description!())]
+enum Command {
+    /// Generates IQ samples of nRF packets, e.g. to test decoding without SDR
+    Generate(GenerateOpt),
+    /// Measures packet error rate and false positives of decoding simulated packets at varying
+    /// signal quality
+    Benchmark(BenchmarkOpt),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(author = crate_authors!(), name = crate_name!(), about = crate_description!(), setting = AppSettings::SubcommandsNegateReqs)]
 struct Opt {
     /// SoapySDR driver name
     #[structopt(short = "d", long = "driver", default_value = "hackrf")]
     driver: String,
 
     /// Channel selection, must be within range [1,125]
-    #[structopt(short = "c", long = "channel", min_values = 1, required = true, validator = range_validator(0, 125), use_delimiter = true)]
+    #[structopt(short = "c", long = "channel", min_values = 1, required_unless_one(&["ble-channels", "scan", "follow"]), validator = range_validator(0, 125), use_delimiter = true)]
     channels: Vec<u8>,
 
+    /// BLE advertising channel selection, decoded in addition to the nRF channels
+    #[structopt(long = "ble", min_values = 1, use_delimiter = true, possible_values = &["37", "38", "39"])]
+    ble_channels: Vec<u8>,
+
     /// Input gain in dBi
     #[structopt(short = "g", long = "gain", default_value = "20")]
     gain: i32,
 
     /// Sample rate in MHz
     #[structopt(short = "s", long = "sample")]
-    sample_rate_mhz: Option<u8>,
+    sample_rate_mhz: Option<f32>,
+
+    /// Center frequency in MHz, derived from selected channels if omitted
+    #[structopt(short = "f", long = "freq")]
+    center_freq_mhz: Option<f32>,
+
+    /// Read IQ samples from recorded file or SigMF recording instead of SoapySDR device
+    #[structopt(short = "i", long = "input")]
+    input: Option<String>,
+
+    /// Sample format of input file
+    #[structopt(long = "format", default_value = "cf32", possible_values = &["cf32", "cs8", "cs16"])]
+    format: SampleFormat,
 
     /// Address length in bytes
     #[structopt(short = "a", long = "alen", default_value = "5", possible_values = &["3", "4", "5"])]
     address_length: usize,
 
-    /// Hexadecimal prefix of the address
-    #[structopt(short = "p", long = "address")]
-    address_prefix: Option<String>,
+    /// Hexadecimal prefixes of the addresses to receive
+    #[structopt(
+        short = "p",
+        long = "address",
+        required_if("crc-length", "0"),
+        validator = address_validator,
+        use_delimiter = true
+    )]
+    address_prefix: Vec<String>,
+
+    /// JSON file of additional addresses to receive, each with its own framing
+    #[structopt(long = "targets", conflicts_with = "promiscuous")]
+    targets_file: Option<String>,
+
+    #[structopt(skip)]
+    targets: Vec<Target>,
 
     /// Payload length in bytes, must be within range [0,32]
-    #[structopt(short = "l", long = "plen", validator = range_validator(0,32), required_unless("shockburst"))]
+    #[structopt(short = "l", long = "plen", validator = range_validator(0,32), required_unless_one(&["shockburst", "promiscuous", "targets-file", "ble-channels"]))]
     payload_length: Option<usize>,
 
     /// Support for Enhanced ShockBurst (ESB) packet headers, allows dynamic payload lengths
     #[structopt(short = "e", long = "shockburst")]
     shockburst: bool,
 
-    /// Data rate (1Mpbs or 2Mpbs)
-    #[structopt(short="r", long = "rate", default_value = "1", possible_values = &["1", "2"])]
-    rate: u8,
+    /// CRC length in bytes, disabling CRC requires an address prefix
+    #[structopt(long = "crc", default_value = "2", possible_values = &["0", "1", "2"])]
+    crc_length: usize,
+
+    /// Corrects single bit errors, but not pairs of bit errors, of packets from addresses given in full with a 2-byte CRC
+    #[structopt(long = "correct")]
+    correct: bool,
+
+    /// Corrects bit errors of packets from addresses given in full with a 2-byte CRC by flipping their least confident bits
+    #[structopt(long = "soft")]
+    soft: bool,
+
+    /// Also reports packets from addresses given in full that fail the CRC check
+    #[structopt(long = "crc-errors")]
+    crc_errors: bool,
+
+    /// Tries all address lengths, payload lengths and framings and reports the one passing CRC
+    #[structopt(long = "promiscuous", conflicts_with_all = &["shockburst", "payload-length"])]
+    promiscuous: bool,
+
+    /// Data rate (250kbps, 1Mpbs or 2Mpbs)
+    #[structopt(short="r", long = "rate", default_value = "1", possible_values = &["250k", "1", "2"])]
+    rate: DataRate,
 
     /// Prints statistics about received packets instead of full packet payloads
     #[structopt(long = "discover")]
     discover: bool,
+
+    /// Ranks discovered addresses by number of packets or mean signal power
+    #[structopt(long = "rank-by", default_value = "count", possible_values = &["count", "power"])]
+    rank_by: Ranking,
+
+    /// Merges copies of a packet received within given time window in milliseconds
+    #[structopt(long = "dedup")]
+    dedup_window_ms: Option<f32>,
+
+    /// Output format of received packets
+    #[structopt(long = "output-format", default_value = "text", possible_values = &["text", "jsonl"])]
+    output_format: OutputFormat,
+
+    /// Sweeps the SDR across the selected channels, or all channels if none are selected
+    #[structopt(long = "scan", conflicts_with_all = &["input", "center-freq-mhz", "ble-channels", "record"])]
+    scan: bool,
+
+    /// Scans for the device with the given address and follows it while it hops across channels
+    #[structopt(long = "follow", requires = "address-prefix", conflicts_with_all = &["input", "center-freq-mhz", "ble-channels", "record"])]
+    follow: bool,
+
+    /// Time in milliseconds to dwell on each frequency while scanning
+    #[structopt(long = "dwell", default_value = "500")]
+    dwell_ms: f32,
+
+    /// Records raw IQ samples as SigMF recording with given base name
+    #[structopt(long = "record")]
+    record: Option<String>,
+
+    /// Adds received packets as annotations to SigMF metadata of recording or input
+    #[structopt(long = "annotate")]
+    annotate: bool,
+
+    /// Writes received packets as pcapng to file or named pipe
+    #[structopt(long = "pcap")]
+    pcap: Option<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
 impl Opt {
-    // returns highest and lowest channel
+    // options with their default values, as parsed if only a subcommand is given, which lifts the
+    // requirement of channels and framing
+    fn defaults() -> Self {
+        let mut opt =
+            Opt::from_iter_safe([crate_name!(), "benchmark"]).expect("Default options are invalid");
+        opt.command = None;
+        opt
+    }
+
+    // nRF channels followed by BLE advertising channels
+    fn all_channels(&self) -> Vec<Channel> {
+        if self.scan {
+            let base = self.scan_decode_base();
+            return (base..=base.saturating_add(self.scan_width()).min(125))
+                .map(Channel::Nrf)
+                .collect();
+        }
+        let nrf = self.channels.iter().map(|c| Channel::Nrf(*c));
+        let ble = self.ble_channels.iter().map(|c| Channel::Ble(*c));
+        nrf.chain(ble).collect()
+    }
+
+    // returns highest and lowest RF channel
     fn min_max_channel(&self) -> (u8, u8) {
-        let mut channels = self.channels.clone();
+        let mut channels: Vec<u8> = self.all_channels().iter().map(|c| c.rf_channel()).collect();
         channels.sort();
         (channels[0], channels[channels.len() - 1])
     }
 
+    // covers all selected channels including the bandwidth of their filters, in whole MHz
     fn sample_rate(&self) -> f32 {
+        if self.scan {
+            return self.sample_rate_mhz.unwrap_or(SCAN_SAMPLE_RATE_MHZ) * 1_000_000.0;
+        }
+        let channels = self.all_channels();
         let (min, max) = self.min_max_channel();
-        self.sample_rate_mhz
-            .map_or((max - min) as f32 + 2.0, |s| s as f32)
-            * 1_000_000.0
+        let margin = self.filter_margin(&channels);
+        self.sample_rate_mhz.unwrap_or((max - min) as f32 + margin) * 1_000_000.0
+    }
+
+    // bandwidth of the widest filter of the channels, in whole MHz
+    fn filter_margin(&self, channels: &[Channel]) -> f32 {
+        let margin = channels
+            .iter()
+            .map(|c| {
+                let (rate, deviation) = self.modulation(*c);
+                let (cutoff, twidth) = channel_filter(rate, deviation);
+                2.0 * cutoff + twidth
+            })
+            .fold(0.0, f32::max);
+        (margin / 1_000_000.0).ceil()
+    }
+
+    // channels selected for scanning
+    fn scan_channels(&self) -> Vec<u8> {
+        if self.channels.is_empty() {
+            (0..=125).collect()
+        } else {
+            self.channels.iter().copied().sorted().dedup().collect()
+        }
+    }
+
+    // number of channels above the lowest one covered by a single frequency of a sweep
+    fn scan_width(&self) -> u8 {
+        let margin = self.filter_margin(&[Channel::Nrf(0)]);
+        (self.sample_rate() / 1_000_000.0 - margin).max(0.0) as u8
+    }
+
+    // lowest channel covered by each frequency of a sweep
+    fn scan_bases(&self) -> Vec<u8> {
+        let width = self.scan_width();
+        let mut bases: Vec<u8> = vec![];
+        for channel in self.scan_channels() {
+            if bases
+                .last()
+                .is_none_or(|b| channel > b.saturating_add(width))
+            {
+                bases.push(channel);
+            }
+        }
+        bases
+    }
+
+    // lowest channel frames are decoded as while scanning, as if receiving on the first frequency
+    // of the sweep, moved down so that the channels it covers do not exceed channel 125
+    fn scan_decode_base(&self) -> u8 {
+        self.scan_bases()[0].min(125u8.saturating_sub(self.scan_width()))
+    }
+
+    // data rate and frequency deviation used on a channel
+    fn modulation(&self, channel: Channel) -> (DataRate, f32) {
+        match channel {
+            Channel::Nrf(_) => (self.rate, self.rate.deviation()),
+            // BLE advertising always uses 1 Mbps
+            Channel::Ble(_) => (DataRate::Mbps1, BLE_DEVIATION),
+        }
+    }
+
+    // selected channels grouped by data rate and frequency deviation
+    fn modulation_groups(&self) -> Vec<((DataRate, f32), Vec<Channel>)> {
+        let mut groups: Vec<((DataRate, f32), Vec<Channel>)> = vec![];
+        for channel in self.all_channels() {
+            let modulation = self.modulation(channel);
+            match groups.iter_mut().find(|(m, _)| *m == modulation) {
+                Some((_, channels)) => channels.push(channel),
+                None => groups.push((modulation, vec![channel])),
+            }
+        }
+        groups
+    }
+
+    // pairs of selected nRF channels whose signals overlap at the selected data rate
+    fn overlapping_channels(&self) -> Vec<(u8, u8)> {
+        self.channels
+            .iter()
+            .copied()
+            .sorted()
+            .dedup()
+            .tuple_windows()
+            .filter(|(a, b)| b - a < self.rate.channel_spacing())
+            .collect()
+    }
+
+    // occupied bandwidth of a single nRF channel and of a BLE advertising channel
+    fn channel_bandwidths(&self) -> (f32, f32) {
+        let bandwidth = |channel| {
+            let (rate, deviation) = self.modulation(channel);
+            occupied_bandwidth(rate, deviation)
+        };
+        (bandwidth(Channel::Nrf(0)), bandwidth(Channel::Ble(37)))
+    }
+
+    fn rate_kbps(&self) -> u16 {
+        self.rate.kbps()
     }
 
     fn center_freq(&self) -> f32 {
+        if self.scan {
+            return sweep_source::center_freq(self.scan_decode_base(), self.scan_width()) as f32;
+        }
         let (min, max) = self.min_max_channel();
-        2_400_000_000.0 + 1_000_000.0 * (min + max) as f32 / 2.0
+        self.center_freq_mhz.map_or(
+            2_400_000_000.0 + 1_000_000.0 * (min as f32 + max as f32) / 2.0,
+            |f| f * 1_000_000.0,
+        )
     }
 
-    fn address_prefix_bytes(&self) -> Vec<u8> {
-        self.address_prefix.clone().map_or(vec![], |prefix| {
-            hex::decode(prefix).expect("Could not parse address prefix")
-        })
+    fn address_prefixes(&self) -> Vec<Vec<u8>> {
+        self.address_prefix
+            .iter()
+            .map(|prefix| hex::decode(prefix).expect("Could not parse address prefix"))
+            .collect()
+    }
+
+    // addresses given on the command line using its framing, followed by those of the targets
+    // file, without any addresses all packets of the command line framing are received
+    fn targets(&self) -> Result<Vec<Target>, Error> {
+        let mut prefixes = self.address_prefixes();
+        if prefixes.is_empty() && self.targets.is_empty() {
+            prefixes.push(vec![]);
+        }
+        let mut targets = Vec::new();
+        for address in prefixes {
+            let target = Target {
+                address,
+                address_length: self.address_length,
+                payload_length: self.payload_length,
+                shockburst: self.shockburst,
+                crc_length: self.crc_length,
+            };
+            target.validate()?;
+            targets.push(target);
+        }
+        targets.extend(self.targets.iter().cloned());
+        Ok(targets)
+    }
+
+    // rate of noise passing as frames of any target on any of the nRF channels
+    fn noise_model(&self) -> Result<NoiseModel, Error> {
+        // while scanning, each channel is received only while its frequency of the sweep is tuned
+        let channels = if self.scan {
+            self.scan_channels().len() as f64 / self.scan_bases().len() as f64
+        } else {
+            self.all_channels()
+                .iter()
+                .filter(|c| matches!(c, Channel::Nrf(_)))
+                .count() as f64
+        };
+        let bit_rate = channels * self.rate.bits_per_second() as f64;
+        let mut noise = NoiseModel::default();
+        if self.promiscuous {
+            let mut prefixes = self.address_prefixes();
+            if prefixes.is_empty() {
+                prefixes.push(vec![]);
+            }
+            for address in prefixes {
+                // ESB frames of every address length, and fixed-length frames of all 35 lengths
+                // from 3 to 37 bytes, split at the given address length
+                for address_length in 3..=5 {
+                    let target = Target {
+                        address: address.clone(),
+                        address_length,
+                        payload_length: None,
+                        shockburst: true,
+                        crc_length: self.crc_length,
+                    };
+                    noise.add(&address, address_length, bit_rate, target.matched_bits());
+                }
+                let target = Target {
+                    address: address.clone(),
+                    address_length: self.address_length,
+                    payload_length: Some(0),
+                    shockburst: false,
+                    crc_length: self.crc_length,
+                };
+                noise.add(
+                    &address,
+                    self.address_length,
+                    35.0 * bit_rate,
+                    target.matched_bits(),
+                );
+            }
+            return Ok(noise);
+        }
+        for target in self.targets()? {
+            noise.add(
+                &target.address,
+                target.address_length,
+                bit_rate,
+                target.matched_bits(),
+            );
+        }
+        Ok(noise)
     }
 }
 
-fn process_channel(
+// splits the input into channels sharing the same modulation and processes each of them
+fn process_channels(
     graph: &mut MTGraph,
     opt: &Opt,
-    center_freq: f32,
     input: Streamp<Complex>,
-    channel: u8,
-) -> NoCopyStreamp<NrfFrame> {
-    let channel_freq = 2_400_000_000.0 + 1_000_000.0 * channel as f32;
+    (rate, deviation): (DataRate, f32),
+    channels: &[Channel],
+    start_time: SystemTime,
+) -> Result<Vec<NoCopyStreamp<NrfFrame>>, Error> {
+    let sample_rate = opt.sample_rate();
+    let samples_per_symbol = sample_rate / rate.bits_per_second();
+    // symbol timing is recovered by interpolating between samples of the same symbol
+    if samples_per_symbol < 2.0 {
+        return Err(Error::new(
+            "Sample rate must be at least twice the data rate",
+        ));
+    }
+    // channels are decimated to reduce the load of demodulation
+    let decimation = ((samples_per_symbol / MIN_SAMPLES_PER_SYMBOL) as usize).max(1);
 
-    let shifted = if channel_freq == center_freq {
-        input
-    } else {
-        let shift_source = add_block!(
-            graph,
-            SignalSourceComplex::new(opt.sample_rate(), center_freq - channel_freq, 1.0)
-        );
-        add_block!(graph, Multiply::new(input, shift_source))
+    // offsets of the channels from the center frequency in kHz, each one needs to be at the
+    // center of a bin of the channelizer
+    let center_freq = opt.center_freq() as f64;
+    let mut offsets: Vec<i64> = channels
+        .iter()
+        .map(|c| ((channel_freq(c.rf_channel()) - center_freq) / 1000.0).round() as i64)
+        .collect();
+    let sample_rate_khz = (sample_rate / 1000.0).round() as u64;
+    let (input, (bins, channel_bins)) = match channelizer::bins(sample_rate_khz, &offsets) {
+        Some(bins) => (input, bins),
+        None => {
+            // move the channels onto a grid of whole MHz
+            let residual = (offsets[0] + 500).rem_euclid(1000) - 500;
+            offsets.iter_mut().for_each(|o| *o -= residual);
+            let bins = channelizer::bins(sample_rate_khz, &offsets)
+                .ok_or_else(|| Error::new("Sample rate is not supported for channelizing"))?;
+            let shifted = add_block!(
+                graph,
+                FreqShift::new(input, sample_rate, -1000.0 * residual as f32)
+            );
+            (shifted, bins)
+        }
     };
 
-    let low_pass = add_block!(
+    let (cutoff, twidth) = channel_filter(rate, deviation);
+    let taps = rustradio::fir::low_pass(sample_rate, cutoff, twidth, &WindowType::Hamming);
+    let outputs = add_block!(
         graph,
-        FftFilter::new(
-            shifted,
-            &rustradio::fir::low_pass_complex(
-                opt.sample_rate(),
-                1_000_000.0, // cut-off: 1M
-                250_000.0,   // twidth: 250k
-                &WindowType::Hamming,
-            ),
-        )
+        Channelizer::new(input, &taps, bins, decimation, &channel_bins)
     );
 
-    let quad_demod = add_block!(graph, QuadratureDemod::new(low_pass, 1.0));
+    let timing = StreamTiming {
+        sample_rate: sample_rate as f64,
+        samples_per_symbol: samples_per_symbol as f64,
+        // group delay of low pass filter
+        delay: ((taps.len() - 1) / 2) as f64,
+        decimation: decimation as f64,
+        start_time: Some(start_time),
+    };
+    zip(outputs, channels)
+        .map(|(samples, channel)| process_channel(graph, opt, samples, *channel, timing.clone()))
+        .collect()
+}
+
+// demodulates and deframes a single channel from its filtered and decimated samples
+fn process_channel(
+    graph: &mut MTGraph,
+    opt: &Opt,
+    input: Streamp<Complex>,
+    channel: Channel,
+    timing: StreamTiming,
+) -> Result<NoCopyStreamp<NrfFrame>, Error> {
+    // filtered samples are used to measure the signal of received frames
+    let (low_pass, probe_samples) = add_ending_block!(graph, input.clone(), Tee::new(input));
 
-    let clock_recovery = add_block!(
+    let quad_demod =
+        add_ending_block!(graph, low_pass.clone(), QuadratureDemod::new(low_pass, 1.0));
+
+    let (clock_recovery, positions) = add_block!(
         graph,
-        ZeroCrossing::new(
-            quad_demod,
-            opt.sample_rate() / (opt.rate as f32 * 1_000_000.0), // Samples per symbol
-            0.0,                                                 // Max deviation (unused)
-        )
+        SymbolSync::new(quad_demod, timing.samples_per_symbol / timing.decimation)
     );
 
-    let bin_slice = add_block!(graph, BinarySlicer::new(clock_recovery));
-
-    let config = if opt.shockburst {
-        NrfConfig::shockburst(
-            channel,
-            opt.address_length,
-            opt.payload_length,
-            &opt.address_prefix_bytes(),
-        )
-    } else {
-        NrfConfig::fixed_length(
-            channel,
-            opt.address_length,
-            opt.payload_length.expect(
-                "Either Enhanced ShockBurst needs to enabled or payload length must be defined.",
-            ),
-            &opt.address_prefix_bytes(),
-        )
+    let (frames, frame_bits) = match channel {
+        Channel::Nrf(channel) if opt.promiscuous => {
+            let prefixes = opt.address_prefixes();
+            let patterns = if prefixes.is_empty() {
+                sync_patterns(&[])
+            } else {
+                prefixes
+                    .iter()
+                    .flat_map(|prefix| {
+                        sync_patterns(&BitVec::from_bytes(prefix).iter().collect_vec())
+                    })
+                    .unique()
+                    .collect()
+            };
+            let (candidates, positions) = add_block!(
+                graph,
+                PreambleCorrelator::new(clock_recovery, positions, patterns, WINDOW_BITS)?
+            );
+            let bin_slice =
+                add_ending_block!(graph, candidates.clone(), BinarySlicer::new(candidates));
+            let frames = add_block!(
+                graph,
+                PromiscuousDeframer::new(
+                    bin_slice,
+                    positions,
+                    channel,
+                    opt.address_length,
+                    &prefixes,
+                    opt.crc_length,
+                    timing.clone()
+                )
+            );
+            (frames, WINDOW_BITS)
+        }
+        Channel::Nrf(channel) => {
+            let configs: Vec<_> = opt
+                .targets()?
+                .iter()
+                .map(|target| {
+                    target
+                        .config(channel)
+                        .timing(timing.clone())
+                        .error_correction(opt.correct)
+                        .soft_decision(opt.soft)
+                        .report_crc_errors(opt.crc_errors)
+                })
+                .collect();
+            let patterns = configs
+                .iter()
+                .flat_map(NrfConfig::sync_patterns)
+                .unique()
+                .collect();
+            let window_len = configs
+                .iter()
+                .map(NrfConfig::max_frame_bits)
+                .max()
+                .unwrap_or(0);
+            let (candidates, positions) = add_block!(
+                graph,
+                PreambleCorrelator::new(clock_recovery, positions, patterns, window_len)?
+            );
+            let frames = add_block!(graph, NrfDeframer::new(candidates, positions, configs));
+            (frames, window_len)
+        }
+        Channel::Ble(index) => {
+            let bin_slice = add_ending_block!(
+                graph,
+                clock_recovery.clone(),
+                BinarySlicer::new(clock_recovery)
+            );
+            let frames = add_block!(
+                graph,
+                BleDeframer::new(bin_slice, positions, index, timing.clone())
+            );
+            (frames, ble_deframer::MAX_FRAME_BITS)
+        }
     };
-
-    add_block!(graph, NrfDeframer::new(bin_slice, config))
+    let frame_samples =
+        frame_bits * (timing.samples_per_symbol / timing.decimation).ceil() as usize;
+    Ok(add_block!(
+        graph,
+        SignalProbe::new(
+            frames,
+            probe_samples,
+            (timing.sample_rate / timing.decimation) as f32,
+            timing.delay as u64,
+            timing.decimation as u64,
+            frame_samples
+        )
+    ))
 }
 
 pub fn main() -> Result<(), Error> {
-    let options = Opt::from_args();
+    let mut options = Opt::from_args();
+    match &options.command {
+        Some(Command::Generate(generate)) => return generate::run(generate),
+        Some(Command::Benchmark(benchmark)) => return benchmark::run(benchmark),
+        None => {}
+    }
+    if let Some(path) = &options.targets_file {
+        options.targets = targets::load(path)?;
+    }
+    // following a device starts with a scan
+    options.scan |= options.follow;
+
+    // metadata of SigMF input takes precedence over command line, decoded frames may be added
+    // as annotations to it later on
+    let mut sigmf_meta = None;
+    if let Some(base) = options.input.as_deref().and_then(sigmf::base_name) {
+        let meta = Meta::load(base)?;
+        options.format = meta.sample_format()?;
+        options.sample_rate_mhz = meta
+            .global
+            .sample_rate
+            .map(|s| (s / 1_000_000.0) as f32)
+            .or(options.sample_rate_mhz);
+        options.center_freq_mhz = meta
+            .frequency()
+            .map(|f| (f / 1_000_000.0) as f32)
+            .or(options.center_freq_mhz);
+        sigmf_meta = Some((base.to_string(), meta));
+    }
+
+    if options.input.is_some()
+        && (options.sample_rate_mhz.is_none() || options.center_freq_mhz.is_none())
+    {
+        return Err(Error::new(
+            "Sample rate and center frequency must be provided for recorded input",
+        ));
+    }
+
+    if options.promiscuous && options.crc_length == 0 {
+        return Err(Error::new("Promiscuous mode requires CRC checksums"));
+    }
+
+    if options.annotate && options.record.is_none() && sigmf_meta.is_none() {
+        return Err(Error::new(
+            "Annotations require either a SigMF recording or a SigMF input",
+        ));
+    }
+
+    // channels of different frequencies of a sweep are not received at the same time
+    for (a, b) in options
+        .overlapping_channels()
+        .into_iter()
+        .filter(|_| !options.scan)
+    {
+        eprintln!(
+            "Warning: channels {} and {} overlap at {} kbps, packets may be received on both",
+            a,
+            b,
+            options.rate_kbps()
+        );
+    }
 
     let mut graph = MTGraph::new();
 
-    eprintln!(
-        "Selected center frequency: {} MHz",
-        options.center_freq() / 1_000_000.0
-    );
+    if options.scan {
+        let bases = options.scan_bases();
+        eprintln!(
+            "Scanning {} channels in {} steps of {} MHz, dwelling {} ms each",
+            options.scan_channels().len(),
+            bases.len(),
+            options.scan_width() + 1,
+            options.dwell_ms
+        );
+    } else {
+        eprintln!(
+            "Selected center frequency: {} MHz",
+            options.center_freq() / 1_000_000.0
+        );
+    }
     eprintln!(
         "Selected sample rate: {} MHz",
         options.sample_rate() / 1_000_000.0
     );
 
-    let mut sources = vec![add_block!(
-        graph,
-        SoapySdrSourceBuilder::new(
-            options.driver.clone(),
-            options.center_freq() as f64,
-            options.sample_rate() as f64
-        )
-        .igain(options.gain as f64)
-        .build()?
-    )];
+    let schedule = Schedule::default();
+    let tuning = Arc::new(Mutex::new(Tuning::Sweep));
+    let position = Position::default();
+    let source = match &options.input {
+        Some(input) => {
+            let path = sigmf::base_name(input).map_or(input.clone(), sigmf::data_path);
+            let file = add_ending_block!(graph, (), FileSource::<u8>::new(&path, false)?);
+            add_block!(graph, IqDecode::new(file, options.format))
+        }
+        None if options.scan => add_block!(
+            graph,
+            SweepSource::new(
+                &options.driver,
+                options.scan_bases(),
+                options.scan_width(),
+                options.sample_rate() as f64,
+                options.gain as f64,
+                Duration::from_secs_f32(options.dwell_ms / 1000.0),
+                schedule.clone(),
+                tuning.clone(),
+                position.clone()
+            )?
+        ),
+        None => add_block!(
+            graph,
+            SoapySdrSourceBuilder::new(
+                options.driver.clone(),
+                options.center_freq() as f64,
+                options.sample_rate() as f64
+            )
+            .igain(options.gain as f64)
+            .build()?
+        ),
+    };
+    // sweep sources count their samples themselves
+    let source = if options.scan {
+        source
+    } else {
+        add_block!(graph, SampleCounter::new(source, position.clone()))
+    };
 
-    // split source for each channel
-    while sources.len() < options.channels.len() {
+    // record raw samples, annotations are added to the recording instead of the input
+    let source = match &options.record {
+        Some(record) => {
+            let hw = options
+                .input
+                .is_none()
+                .then(|| format!("{} (gain {} dB)", options.driver, options.gain));
+            let meta = Meta::recording(
+                options.sample_rate() as f64,
+                options.center_freq() as f64,
+                hw,
+            );
+            meta.save(record)?;
+            sigmf_meta = Some((record.clone(), meta));
+
+            let (a, b) = add_ending_block!(graph, source.clone(), Tee::new(source));
+            graph.add(Box::new(FileSink::new(
+                b,
+                sigmf::data_path(record).into(),
+                Mode::Overwrite,
+            )?));
+            a
+        }
+        None => source,
+    };
+
+    let mut sources = vec![source];
+
+    // split source for each group of channels sharing the same modulation
+    let groups = options.modulation_groups();
+    while sources.len() < groups.len() {
         if let Some(source) = sources.pop() {
-            let (a, b) = add_block!(graph, Tee::new(source));
+            let (a, b) = add_ending_block!(graph, source.clone(), Tee::new(source));
             sources.push(a);
             sources.push(b);
         }
     }
 
+    // recordings start at the time stored in their metadata, otherwise at the time of decoding
+    let start_time = sigmf_meta
+        .as_ref()
+        .and_then(|(_, meta)| meta.datetime())
+        .unwrap_or_else(SystemTime::now);
+
     // process individual channels
-    let processed: Vec<NoCopyStreamp<NrfFrame>> = zip(sources, options.channels.iter().copied())
-        .map(|(source, channel)| {
-            process_channel(&mut graph, &options, options.center_freq(), source, channel)
+    let processed: Vec<NoCopyStreamp<NrfFrame>> = zip(sources, groups)
+        .map(|(source, (modulation, channels))| {
+            process_channels(
+                &mut graph, &options, source, modulation, &channels, start_time,
+            )
         })
-        .collect();
+        .flatten_ok()
+        .collect::<Result<_, _>>()?;
 
     // union all received messages
     let union = processed
@@ -223,13 +960,100 @@ pub fn main() -> Result<(), Error> {
         .reduce(|a, b| add_block!(graph, Union::new(a, b)))
         .expect("At least one channel must be provided");
 
+    // channels of frames received while scanning
+    let scan_counts = Arc::new(Mutex::new(BTreeMap::new()));
+    let union = if options.scan {
+        add_block!(
+            graph,
+            ScanTracker::new(
+                union,
+                options.scan_decode_base(),
+                options.scan_channels(),
+                schedule,
+                scan_counts.clone()
+            )
+        )
+    } else {
+        union
+    };
+
+    // hop sequence of the followed device
+    let hop_model = Arc::new(Mutex::new(HopModel::default()));
+    let union = if options.follow {
+        add_block!(
+            graph,
+            HopFollower::new(
+                union,
+                tuning,
+                options.address_prefixes(),
+                position.clone(),
+                options.scan_width(),
+                options.sample_rate(),
+                hop_model.clone()
+            )
+        )
+    } else {
+        union
+    };
+
+    let union = match options.dedup_window_ms {
+        Some(window_ms) => add_block!(
+            graph,
+            Dedup::new(
+                union,
+                Duration::from_secs_f32(window_ms / 1000.0),
+                options.sample_rate(),
+                position.clone()
+            )
+        ),
+        None => union,
+    };
+
+    let annotations = Arc::new(Mutex::new(Vec::new()));
+    let union = if options.annotate {
+        add_block!(
+            graph,
+            SigMFAnnotator::new(union, options.channel_bandwidths(), annotations.clone())
+        )
+    } else {
+        union
+    };
+
+    // write packets to pcapng in addition to any other output
+    let union = match &options.pcap {
+        Some(path) => {
+            let (a, b) = add_block!(graph, NoCopyTee::new(union));
+            graph.add(Box::new(PcapSink::new(b, path, options.rate_kbps())?));
+            a
+        }
+        None => union,
+    };
+
     // output receives messages
     if options.discover {
-        graph.add(Box::new(NrfStatSink::new(union)));
+        graph.add(Box::new(NrfStatSink::new(
+            union,
+            options.rank_by,
+            options.noise_model()?,
+            options.sample_rate(),
+        )));
+    } else if let OutputFormat::Jsonl = options.output_format {
+        graph.add(Box::new(JsonSink::new(union)));
+    } else if options.promiscuous {
+        println!(" Ch Addr [PID NO_ACK] Payload [Framing]");
+        graph.add(Box::new(StdoutSink::new(union)));
+    } else if !options.targets.is_empty() {
+        println!(" Ch Addr [PID NO_ACK] Payload");
+        graph.add(Box::new(StdoutSink::new(union)));
     } else {
         println!(
-            " Ch Addr{}Payload",
-            iter::repeat_n(' ', options.address_length * 2 - 3).collect::<String>()
+            " Ch Addr{}{}Payload",
+            iter::repeat_n(' ', options.address_length * 2 - 3).collect::<String>(),
+            if options.shockburst {
+                "PID NO_ACK "
+            } else {
+                ""
+            }
         );
         graph.add(Box::new(StdoutSink::new(union)));
     }
@@ -244,5 +1068,86 @@ pub fn main() -> Result<(), Error> {
     let st = std::time::Instant::now();
     graph.run()?;
     eprintln!("{}", graph.generate_stats(st.elapsed()));
+
+    if options.scan {
+        let counts = scan_counts.lock().unwrap();
+        eprintln!(
+            "Channels with valid packets: {}",
+            if counts.is_empty() {
+                "none".to_string()
+            } else {
+                counts
+                    .iter()
+                    .map(|(channel, count)| format!("{} ({})", channel, count))
+                    .join(", ")
+            }
+        );
+    }
+    if options.follow {
+        eprintln!("Hops of followed device: {}", hop_model.lock().unwrap());
+    }
+
+    if let Some((base, mut meta)) = sigmf_meta.filter(|_| options.annotate) {
+        // replace annotations of previous runs
+        meta.annotations
+            .retain(|a| a.generator.as_deref() != Some(env!("CARGO_PKG_NAME")));
+        meta.annotations.append(&mut annotations.lock().unwrap());
+        meta.annotations.sort_by_key(|a| a.sample_start);
+        meta.save(&base)?;
+    }
     Ok(())
 }
+
+#[test]
+fn test_sample_rate() {
+    let process = |args: &[&str]| {
+        let opt = Opt::from_iter(
+            ["nrf-probe", "--channel", "40", "--plen", "0"]
+                .iter()
+                .chain(args),
+        );
+        let channel = Channel::Nrf(40);
+        process_channels(
+            &mut MTGraph::new(),
+            &opt,
+            rustradio::stream::Stream::newp(),
+            opt.modulation(channel),
+            &[channel],
+            SystemTime::now(),
+        )
+    };
+    assert!(process(&["--rate", "2", "--sample", "2"]).is_err());
+    assert!(process(&["--rate", "2", "--sample", "3"]).is_err());
+    assert!(process(&["--rate", "2", "--sample", "4"]).is_ok());
+}
+
+#[test]
+fn test_scan_top_channel() {
+    let opt = Opt::from_iter(["nrf-probe", "--scan", "--channel", "125", "--plen", "4"]);
+    // 10 MHz cover 9 channels of 1 Mbps, decoded below the top channel instead of beyond it
+    let channels = opt
+        .all_channels()
+        .iter()
+        .map(Channel::rf_channel)
+        .collect_vec();
+    assert_eq!(channels, (117..=125).collect_vec());
+    assert_eq!(opt.scan_bases(), vec![125]);
+    assert_eq!(opt.center_freq(), channel_freq(121) as f32);
+}
+
+#[test]
+fn test_address_prefix() {
+    let parse = |address: &str| {
+        Opt::from_iter_safe([
+            "nrf-probe",
+            "--promiscuous",
+            "--channel",
+            "40",
+            "-p",
+            address,
+        ])
+    };
+    assert!(parse("0102030405").is_ok());
+    assert!(parse("0102030405060708090a").is_err());
+    assert!(parse("01x2").is_err());
+}
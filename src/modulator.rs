@@ -0,0 +1,113 @@
+use rustradio::Complex;
+use std::f64::consts::PI;
+
+// bandwidth-time product of the Gaussian filter of nRF24 transceivers
+const BT: f64 = 0.5;
+
+// number of neighbouring symbols on each side overlapping a symbol after Gaussian filtering
+const SPAN: i64 = 2;
+
+// error function, approximated with a maximum error of 1.5e-7 (Abramowitz and Stegun 7.1.26)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+// response of the Gaussian filter to a rectangular pulse of one symbol centered at zero, time in
+// symbols
+fn gaussian_pulse(t: f64) -> f64 {
+    let k = PI * BT * (2.0 / 2f64.ln()).sqrt();
+    0.5 * (erf(k * (t + 0.5)) - erf(k * (t - 0.5)))
+}
+
+/// GFSK modulator turning bits into complex baseband samples
+///
+/// The frequency of every symbol is shaped by a Gaussian filter and the phase is continuous
+/// across symbols, as transmitted by nRF24 transceivers.
+#[derive(Debug, Clone)]
+pub struct Modulator {
+    sample_rate: f64,
+    bit_rate: f64,
+    deviation: f64,
+    // relative error of the symbol rate, e.g. due to an inaccurate crystal
+    drift: f64,
+}
+
+impl Modulator {
+    pub fn new(sample_rate: f64, bit_rate: f64, deviation: f64) -> Self {
+        Self {
+            sample_rate,
+            bit_rate,
+            deviation,
+            drift: 0.0,
+        }
+    }
+
+    /// Symbol rate error in parts per million
+    pub fn drift(mut self, ppm: f64) -> Self {
+        self.drift = ppm / 1_000_000.0;
+        self
+    }
+
+    // samples per symbol including drift
+    pub fn samples_per_symbol(&self) -> f64 {
+        self.sample_rate / (self.bit_rate * (1.0 + self.drift))
+    }
+
+    /// Modulates bits onto a carrier at the given frequency relative to the center frequency
+    pub fn modulate(&self, bits: &[bool], freq: f64) -> Vec<Complex> {
+        let samples_per_symbol = self.samples_per_symbol();
+        let len = (bits.len() as f64 * samples_per_symbol).ceil() as usize;
+        let mut phase: f64 = 0.0;
+        (0..len)
+            .map(|n| {
+                // time in symbols, the first symbol starts at zero
+                let t = n as f64 / samples_per_symbol;
+                let symbol = t.floor() as i64;
+                let shaped: f64 = (symbol - SPAN..=symbol + SPAN)
+                    .filter_map(|k| {
+                        let bit = bits.get(usize::try_from(k).ok()?)?;
+                        let level = if *bit { 1.0 } else { -1.0 };
+                        Some(level * gaussian_pulse(t - k as f64 - 0.5))
+                    })
+                    .sum();
+                let sample = Complex::new(phase.cos() as f32, phase.sin() as f32);
+                phase += 2.0 * PI * (freq + shaped * self.deviation) / self.sample_rate;
+                phase %= 2.0 * PI;
+                sample
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_modulator() {
+    assert!((erf(0.5) - 0.5204998778).abs() < 1e-6);
+    assert!((erf(-1.5) + 0.9661051465).abs() < 1e-6);
+    // symbol is spread to neighbours, but its energy is kept
+    let energy: f64 = (-30..=30)
+        .map(|i| gaussian_pulse(i as f64 / 10.0) / 10.0)
+        .sum();
+    assert!((energy - 1.0).abs() < 1e-3);
+
+    let bits: Vec<bool> = [0xaa, 0x3c, 0x0f]
+        .iter()
+        .flat_map(|b: &u8| (0..8).rev().map(move |i| b >> i & 1 == 1))
+        .collect();
+    let modulator = Modulator::new(4e6, 1e6, 160e3);
+    let samples = modulator.modulate(&bits, 250e3);
+    assert_eq!(samples.len(), bits.len() * 4);
+    assert!(samples.iter().all(|s| (s.norm() - 1.0).abs() < 1e-3));
+    // frequency at the center of every symbol, above the carrier for ones
+    for (i, bit) in bits.iter().enumerate() {
+        let (a, b) = (samples[i * 4 + 1], samples[i * 4 + 2]);
+        let freq = (b * a.conj()).arg() as f64 * 4e6 / (2.0 * PI);
+        assert_eq!(freq > 250e3, *bit, "symbol {}", i);
+    }
+
+    let modulator = modulator.drift(-1000.0);
+    assert_eq!(modulator.modulate(&bits, 0.0).len(), 97);
+}
@@ -0,0 +1,54 @@
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp};
+use rustradio::Error;
+
+/// Duplicate a stream of messages into two streams
+pub struct NoCopyTee<T> {
+    src: NoCopyStreamp<T>,
+    a: NoCopyStreamp<T>,
+    b: NoCopyStreamp<T>,
+}
+
+impl<T> NoCopyTee<T> {
+    pub fn new(src: NoCopyStreamp<T>) -> Self {
+        Self {
+            src,
+            a: NoCopyStream::newp(),
+            b: NoCopyStream::newp(),
+        }
+    }
+
+    pub fn out(&self) -> (NoCopyStreamp<T>, NoCopyStreamp<T>) {
+        (self.a.clone(), self.b.clone())
+    }
+}
+
+impl<T> Block for NoCopyTee<T>
+where
+    T: Clone,
+{
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        if let Some((val, tags)) = self.src.pop() {
+            self.a.push(val.clone(), &tags);
+            self.b.push(val, &tags);
+            Ok(BlockRet::Ok)
+        } else if self.eof() {
+            Ok(BlockRet::EOF)
+        } else {
+            Ok(BlockRet::Noop)
+        }
+    }
+}
+
+impl<T> BlockEOF for NoCopyTee<T> {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.out())
+    }
+}
+
+impl<T> BlockName for NoCopyTee<T> {
+    fn block_name(&self) -> &str {
+        "NoCopyTee"
+    }
+}
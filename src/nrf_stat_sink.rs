@@ -1,21 +1,189 @@
-use crate::deframer::NrfFrame;
+use crate::deframer::{NrfFrame, Signal};
 use itertools::Itertools;
 use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
 use rustradio::stream::NoCopyStreamp;
 use rustradio::Error;
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+// noise frames are received just above the noise floor, addresses are trusted fully from this SNR
+// above it on
+const NOISE_SNR: f32 = 3.0;
+const SNR_MARGIN: f32 = 6.0;
+
+// addresses with lower confidence are likely noise and shown last
+const MIN_CONFIDENCE: f32 = 0.5;
+
+/// Order of addresses in the statistics table
+#[derive(Debug, Clone, Copy)]
+pub enum Ranking {
+    // most frequently received addresses first
+    Count,
+    // strongest signals first
+    Power,
+}
+
+impl FromStr for Ranking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Ranking::Count),
+            "power" => Ok(Ranking::Power),
+            _ => Err(format!("Unknown ranking: {}", s)),
+        }
+    }
+}
+
+/// Rate at which noise passes as frames, used to tell addresses of real devices from noise
+#[derive(Debug, Clone, Default)]
+pub struct NoiseModel {
+    // address prefix, address length in bytes and rate of noise frames per second of every
+    // configuration
+    sources: Vec<(Vec<u8>, usize, f64)>,
+}
+
+impl NoiseModel {
+    /// Adds a configuration receiving bits at the given rate, noise passes as a frame if it matches
+    /// the given number of bits by chance
+    pub fn add(&mut self, prefix: &[u8], address_len: usize, bit_rate: f64, matched_bits: f64) {
+        let rate = bit_rate * (-matched_bits).exp2();
+        self.sources.push((prefix.to_vec(), address_len, rate));
+    }
+
+    // expected number of addresses received at least count times from noise within given time,
+    // all of them within the given span, as noise frames are spread evenly across all addresses
+    // matching a configuration and across time
+    fn expected(&self, address: &[u8], count: usize, seconds: f64, span: f64) -> f64 {
+        self.sources
+            .iter()
+            .filter(|(prefix, address_len, _)| {
+                address.len() == *address_len && address.starts_with(prefix)
+            })
+            .map(|(prefix, address_len, rate)| {
+                let addresses = (8.0 * (address_len - prefix.len()) as f64).exp2();
+                let mean = rate * seconds / addresses;
+                // any noise frame may be followed by the others within the span
+                let bursts =
+                    rate * seconds * poisson_tail(rate * span / addresses, count.saturating_sub(1));
+                bursts.min(addresses * poisson_tail(mean, count))
+            })
+            .sum()
+    }
+}
+
+// natural logarithm of n!, using Stirling's approximation for large n
+fn ln_factorial(n: usize) -> f64 {
+    if n < 20 {
+        return (2..=n).map(|k| (k as f64).ln()).sum();
+    }
+    let n = n as f64;
+    n * n.ln() - n + 0.5 * (2.0 * std::f64::consts::PI * n).ln() + 1.0 / (12.0 * n)
+}
+
+// probability of a Poisson distributed variable with given mean being at least n
+fn poisson_tail(mean: f64, n: usize) -> f64 {
+    if n == 0 {
+        return 1.0;
+    }
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    if n as f64 <= mean {
+        // the tail is large, so its complement is summed up without loss of precision
+        let mut term = (-mean).exp();
+        let mut sum = 0.0;
+        for k in 0..n {
+            sum += term;
+            term *= mean / (k + 1) as f64;
+        }
+        return (1.0 - sum).max(0.0);
+    }
+    // terms decrease from the n-th on, the first one is computed in log space to avoid overflow
+    let mut term = (-mean + n as f64 * mean.ln() - ln_factorial(n)).exp();
+    let mut sum = 0.0;
+    let mut k = n;
+    while term > sum * 1e-12 {
+        sum += term;
+        k += 1;
+        term *= mean / k as f64;
+    }
+    sum
+}
+
+// statistics of messages received for a single address
+#[derive(Default)]
+struct AddressStats {
+    count: usize,
+    // messages failing the CRC check, only counted if CRC errors are reported
+    crc_errors: usize,
+    channels: HashSet<u8>,
+    payload_lens: HashSet<usize>,
+    // packet IDs of Enhanced ShockBurst packets
+    pids: HashSet<u8>,
+    // sums of signal measurements and number of measured messages
+    signal_sum: (f32, f32, f32),
+    signal_count: usize,
+    // times of the first and the latest message in seconds since the start of the stream
+    seen: Option<(f64, f64)>,
+}
+
+impl AddressStats {
+    // mean signal quality of all measured messages
+    fn mean_signal(&self) -> Option<Signal> {
+        let n = self.signal_count as f32;
+        (self.signal_count > 0).then(|| Signal {
+            power: self.signal_sum.0 / n,
+            snr: self.signal_sum.1 / n,
+            freq_offset: self.signal_sum.2 / n,
+        })
+    }
+
+    // confidence from 0 to 1 that the address belongs to a real device rather than noise, given
+    // the time since the start of the stream
+    fn confidence(&self, address: &[u8], noise: &NoiseModel, seconds: f64) -> f32 {
+        // unlikely to be received this often by chance, even less so within a short time, as
+        // devices send in bursts while noise frames are spread across time
+        let span = self.seen.map_or(seconds, |(first, last)| last - first);
+        let expected = noise.expected(address, self.count, seconds, span);
+        let chance = 1.0 / (1.0 + expected as f32);
+        // noise frames are spread across all payload lengths, real devices only use a few
+        let consistency = match self.count {
+            0 => 1.0,
+            count => 1.0 - (self.payload_lens.len() - 1) as f32 / count as f32,
+        };
+        let signal = self
+            .mean_signal()
+            .map_or(1.0, |s| ((s.snr - NOISE_SNR) / SNR_MARGIN).clamp(0.0, 1.0));
+        chance * consistency * signal
+    }
+}
 
 pub struct NrfStatSink {
     src: NoCopyStreamp<NrfFrame>,
-    // history of received messages, containing count, channels and payload lengths
-    history: HashMap<Vec<u8>, (usize, HashSet<u8>, HashSet<usize>)>,
+    // history of received messages per address
+    history: HashMap<Vec<u8>, AddressStats>,
+    ranking: Ranking,
+    noise: NoiseModel,
+    sample_rate: f32,
+    // position of the latest frame within the input sample stream
+    position: u64,
 }
 
 impl NrfStatSink {
-    pub fn new(src: NoCopyStreamp<NrfFrame>) -> Self {
+    pub fn new(
+        src: NoCopyStreamp<NrfFrame>,
+        ranking: Ranking,
+        noise: NoiseModel,
+        sample_rate: f32,
+    ) -> Self {
         Self {
             src,
             history: HashMap::new(),
+            ranking,
+            noise,
+            sample_rate,
+            position: 0,
         }
     }
 }
@@ -27,42 +195,110 @@ impl Block for NrfStatSink {
             Some(x) => x,
         };
 
-        match self.history.get_mut(&v.address) {
-            Some((count, channel, payload_len)) => {
-                *count += 1;
-                channel.insert(v.channel);
-                payload_len.insert(v.payload.len());
-            }
-            None => {
-                self.history.insert(
-                    v.address,
-                    (
-                        1,
-                        HashSet::from([v.channel]),
-                        HashSet::from([v.payload.len()]),
-                    ),
-                );
+        self.position = self.position.max(v.sample_start + v.sample_count);
+        let stats = self.history.entry(v.address.clone()).or_default();
+        stats.channels.insert(v.channel);
+        if let Some(duplicates) = &v.duplicates {
+            stats.channels.extend(&duplicates.channels);
+        }
+        // length and PID of messages failing the CRC check may be corrupted
+        if v.crc_ok() {
+            stats.count += 1;
+            let time = v.sample_start as f64 / self.sample_rate as f64;
+            let (first, last) = stats.seen.unwrap_or((time, time));
+            stats.seen = Some((first.min(time), last.max(time)));
+            stats.payload_lens.insert(v.payload.len());
+            if let Some(pcf) = v.pcf {
+                stats.pids.insert(pcf.pid);
             }
-        };
+        } else {
+            stats.crc_errors += 1;
+        }
+        if let Some(signal) = v.signal {
+            stats.signal_sum.0 += signal.power;
+            stats.signal_sum.1 += signal.snr;
+            stats.signal_sum.2 += signal.freq_offset;
+            stats.signal_count += 1;
+        }
 
         print!("\x1B[2J\x1B[1;1H"); // reset terminal
-        println!("Address    | Count | Payload Length | Channels");
+        println!(
+            "Address    | Count | CRC Errors | Confidence | Power (dBFS) | SNR (dB) | Offset (kHz) | Payload Length | PIDs    | Channels"
+        );
 
+        let seconds = self.position as f64 / self.sample_rate as f64;
         self.history
             .iter()
-            .sorted_by_key(|(_, (count, _, _))| count)
+            .map(|(address, stats)| {
+                let confidence = stats.confidence(address, &self.noise, seconds);
+                (address, stats, confidence)
+            })
+            // likely noise last
+            .sorted_by(|(_, a, a_conf), (_, b, b_conf)| {
+                let plausible = |c: f32| c >= MIN_CONFIDENCE;
+                plausible(*a_conf)
+                    .cmp(&plausible(*b_conf))
+                    .then_with(|| match self.ranking {
+                        Ranking::Count => a.count.cmp(&b.count),
+                        Ranking::Power => {
+                            let power =
+                                |s: &AddressStats| s.mean_signal().map_or(f32::MIN, |s| s.power);
+                            power(a).total_cmp(&power(b))
+                        }
+                    })
+            })
             .rev()
             .take(10)
-            .map(|(address, (count, channels, sizes))| {
+            .map(|(address, stats, confidence)| {
+                let signal = stats.mean_signal().map_or(
+                    ("-".to_string(), "-".to_string(), "-".to_string()),
+                    |s| {
+                        (
+                            format!("{:.1}", s.power),
+                            format!("{:.1}", s.snr),
+                            format!("{:+.1}", s.freq_offset / 1000.0),
+                        )
+                    },
+                );
                 (
                     hex::encode(address),
-                    count,
-                    channels.iter().sorted().map(|s| s.to_string()).join(","),
-                    sizes.iter().sorted().map(|s| s.to_string()).join(","),
+                    stats.count,
+                    stats.crc_errors,
+                    (
+                        format!("{:.0}%", confidence * 100.0),
+                        confidence < MIN_CONFIDENCE,
+                    ),
+                    signal,
+                    stats
+                        .channels
+                        .iter()
+                        .sorted()
+                        .map(|s| s.to_string())
+                        .join(","),
+                    stats
+                        .payload_lens
+                        .iter()
+                        .sorted()
+                        .map(|s| s.to_string())
+                        .join(","),
+                    stats.pids.iter().sorted().map(|s| s.to_string()).join(","),
                 )
             })
-            .for_each(|(addr, count, channels, sizes)| {
-                println!("{:10} | {:5} | {:<14} | {}", addr, count, sizes, channels)
+            .for_each(|row| {
+                let (addr, count, crc_errors, (confidence, noise), signal, channels, sizes, pids) =
+                    row;
+                let (power, snr, offset) = signal;
+                // likely noise is greyed out
+                if noise {
+                    print!("\x1B[2m");
+                }
+                println!(
+                    "{:10} | {:5} | {:10} | {:>10} | {:>12} | {:>8} | {:>12} | {:<14} | {:<7} | {}",
+                    addr, count, crc_errors, confidence, power, snr, offset, sizes, pids, channels
+                );
+                if noise {
+                    print!("\x1B[0m");
+                }
             });
 
         Ok(BlockRet::Ok)
@@ -76,3 +312,41 @@ impl BlockName for NrfStatSink {
 }
 
 impl BlockEOF for NrfStatSink {}
+
+#[test]
+fn test_confidence() {
+    assert!((poisson_tail(1.0, 1) - (1.0 - (-1.0f64).exp())).abs() < 1e-12);
+    assert!((poisson_tail(1e-6, 2) - 0.5e-12).abs() < 1e-15);
+    assert!(poisson_tail(100.0, 50) > 0.999);
+    assert!((ln_factorial(30) - (1..=30).map(|k| (k as f64).ln()).sum::<f64>()).abs() < 1e-6);
+
+    // 4-byte addresses of ESB frames with unknown length and 2-byte CRC
+    let mut noise = NoiseModel::default();
+    noise.add(&[], 4, 1e6, 8.0 + 16.0 + (64.0f64 / 33.0).log2());
+    let stats = |count: usize, payload_lens: &[usize], snr: f32| AddressStats {
+        count,
+        payload_lens: payload_lens.iter().copied().collect(),
+        signal_sum: (-50.0 * count as f32, snr * count as f32, 0.0),
+        signal_count: count,
+        ..Default::default()
+    };
+    let address = [1, 2, 3, 4];
+    // about three noise frames are expected within 100 seconds, but not twice the same address
+    assert!(stats(1, &[8], 20.0).confidence(&address, &noise, 100.0) < MIN_CONFIDENCE);
+    assert!(stats(2, &[8], 20.0).confidence(&address, &noise, 100.0) > 0.99);
+    // noise is received just above the noise floor and spread across payload lengths
+    assert!(stats(2, &[8], 1.0).confidence(&address, &noise, 100.0) < MIN_CONFIDENCE);
+    assert!(stats(3, &[8, 20, 27], 20.0).confidence(&address, &noise, 100.0) < MIN_CONFIDENCE);
+    // addresses not matching any configuration are not expected from noise
+    assert!(stats(1, &[8], 20.0).confidence(&[1, 2, 3], &noise, 100.0) > 0.99);
+
+    // 3-byte addresses matched by noise so often that many of them are received twice by chance,
+    // though hardly twice within a short time
+    noise.add(&[], 3, 100.0, 0.0);
+    let seen = |first: f64, last: f64| AddressStats {
+        seen: Some((first, last)),
+        ..stats(2, &[8], 20.0)
+    };
+    assert!(seen(100.0, 900.0).confidence(&[1, 2, 3], &noise, 1000.0) < MIN_CONFIDENCE);
+    assert!(seen(500.0, 500.1).confidence(&[1, 2, 3], &noise, 1000.0) > 0.9);
+}
@@ -0,0 +1,215 @@
+use crate::deframer::NrfFrame;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::NoCopyStreamp;
+use rustradio::Error;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// LINKTYPE_USER0, reserved for private use
+const LINKTYPE_USER0: u16 = 147;
+
+// version of the per-packet header preceding address and payload
+const HEADER_VERSION: u8 = 0;
+
+// flags of the per-packet header
+const FLAG_CRC_OK: u8 = 0x01;
+const FLAG_PCF: u8 = 0x02;
+const FLAG_NO_ACK: u8 = 0x04;
+const FLAG_BLE: u8 = 0x08;
+const FLAG_NO_CRC: u8 = 0x10;
+
+/// Writes frames as pcapng, each packet starting with a header followed by address and payload
+///
+/// Header layout (8 bytes):
+///  * version (u8)
+///  * channel (u8)
+///  * data rate in kbps (u16, big-endian)
+///  * address length in bytes (u8)
+///  * flags (u8): 0x01 CRC valid, 0x02 ESB PCF present, 0x04 NO_ACK set, 0x08 BLE advertising PDU,
+///    0x10 CRC disabled
+///  * payload length from PCF or first byte of BLE PDU header (u8)
+///  * packet ID from PCF or length of BLE PDU (u8)
+///
+/// Received and computed checksum of frames failing the CRC check are given in a packet comment.
+pub struct PcapWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut w: W) -> std::io::Result<Self> {
+        // section header block
+        write_block(&mut w, 0x0a0d0d0a, |b| {
+            b.extend(0x1a2b3c4du32.to_le_bytes()); // byte-order magic
+            b.extend(1u16.to_le_bytes()); // major version
+            b.extend(0u16.to_le_bytes()); // minor version
+            b.extend((-1i64).to_le_bytes()); // section length unspecified
+        })?;
+        // interface description block
+        write_block(&mut w, 0x00000001, |b| {
+            b.extend(LINKTYPE_USER0.to_le_bytes());
+            b.extend(0u16.to_le_bytes()); // reserved
+            b.extend(0u32.to_le_bytes()); // no snap length limit
+            b.extend(9u16.to_le_bytes()); // if_tsresol option
+            b.extend(1u16.to_le_bytes());
+            b.extend([6, 0, 0, 0]); // microseconds, padded
+            b.extend(0u32.to_le_bytes()); // opt_endofopt
+        })?;
+        w.flush()?;
+        Ok(Self { w })
+    }
+
+    pub fn write_frame(
+        &mut self,
+        frame: &NrfFrame,
+        rate_kbps: u16,
+        time: SystemTime,
+    ) -> std::io::Result<()> {
+        let mut data = vec![HEADER_VERSION, frame.channel];
+        data.extend(rate_kbps.to_be_bytes());
+        let (flags, header) = match (frame.pcf, frame.ble) {
+            (Some(pcf), _) => {
+                let no_ack = if pcf.no_ack { FLAG_NO_ACK } else { 0 };
+                (FLAG_PCF | no_ack, [pcf.length, pcf.pid])
+            }
+            (None, Some(ble)) => (FLAG_BLE, [ble.to_byte(), frame.payload.len() as u8]),
+            (None, None) => (0, [0, 0]),
+        };
+        let flags = match frame.crc {
+            Some(_) if frame.crc_ok() => flags | FLAG_CRC_OK,
+            Some(_) => flags,
+            None => flags | FLAG_NO_CRC,
+        };
+        data.extend([frame.address.len() as u8, flags]);
+        data.extend(header);
+        data.extend(&frame.address);
+        data.extend(&frame.payload);
+
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        // enhanced packet block
+        write_block(&mut self.w, 0x00000006, |b| {
+            b.extend(0u32.to_le_bytes()); // interface ID
+            b.extend(((micros >> 32) as u32).to_le_bytes());
+            b.extend((micros as u32).to_le_bytes());
+            b.extend((data.len() as u32).to_le_bytes()); // captured length
+            b.extend((data.len() as u32).to_le_bytes()); // original length
+            b.extend(&data);
+            b.resize(b.len().next_multiple_of(4), 0);
+            if let (Some(crc), Some(computed_crc)) = (frame.crc, frame.computed_crc) {
+                let comment = format!(
+                    "CRC error, received {:#x}, computed {:#x}",
+                    crc, computed_crc
+                );
+                b.extend(1u16.to_le_bytes()); // opt_comment option
+                b.extend((comment.len() as u16).to_le_bytes());
+                b.extend(comment.as_bytes());
+                b.resize(b.len().next_multiple_of(4), 0);
+                b.extend(0u32.to_le_bytes()); // opt_endofopt
+            }
+        })?;
+        // flush each packet to allow live capture through named pipes
+        self.w.flush()
+    }
+}
+
+// writes a pcapng block of given type, body is padded to 32 bits by the caller
+fn write_block<W: Write>(
+    w: &mut W,
+    block_type: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) -> std::io::Result<()> {
+    let mut b = Vec::new();
+    body(&mut b);
+    let len = (b.len() + 12) as u32;
+    w.write_all(&block_type.to_le_bytes())?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(&b)?;
+    w.write_all(&len.to_le_bytes())
+}
+
+pub struct PcapSink {
+    src: NoCopyStreamp<NrfFrame>,
+    writer: PcapWriter<std::fs::File>,
+    rate_kbps: u16,
+}
+
+impl PcapSink {
+    /// Creates sink writing to file or named pipe at given path
+    pub fn new(src: NoCopyStreamp<NrfFrame>, path: &str, rate_kbps: u16) -> Result<Self, Error> {
+        let writer = PcapWriter::new(std::fs::File::create(path)?)?;
+        Ok(Self {
+            src,
+            writer,
+            rate_kbps,
+        })
+    }
+}
+
+impl Block for PcapSink {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (v, _tags) = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+        let time = v.timestamp.unwrap_or_else(SystemTime::now);
+        // BLE advertising always uses 1 Mbps
+        let rate_kbps = if v.ble.is_some() {
+            1000
+        } else {
+            self.rate_kbps
+        };
+        self.writer.write_frame(&v, rate_kbps, time)?;
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for PcapSink {}
+
+impl BlockName for PcapSink {
+    fn block_name(&self) -> &str {
+        "PcapSink"
+    }
+}
+
+#[test]
+fn test_pcapng() {
+    let frame = NrfFrame {
+        channel: 39,
+        address: vec![1, 2, 3, 4, 5],
+        payload: vec![6, 7, 8],
+        crc: Some(0x1234),
+        ..Default::default()
+    };
+    let mut writer = PcapWriter::new(Vec::new()).expect("Writing header failed");
+    writer
+        .write_frame(&frame, 1000, UNIX_EPOCH)
+        .expect("Writing frame failed");
+    let out = writer.w;
+
+    // section header, interface description and enhanced packet block
+    assert_eq!(out.len(), 28 + 32 + 48);
+    assert_eq!(&out[28 + 8..28 + 10], &LINKTYPE_USER0.to_le_bytes());
+    let packet = &out[60 + 28..60 + 28 + 16];
+    assert_eq!(&packet[..8], &[0, 39, 0x03, 0xe8, 5, FLAG_CRC_OK, 0, 0]);
+    assert_eq!(&packet[8..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(&out[out.len() - 4..], &48u32.to_le_bytes());
+
+    let frame = NrfFrame {
+        computed_crc: Some(0x5678),
+        ..frame
+    };
+    let mut writer = PcapWriter::new(Vec::new()).expect("Writing header failed");
+    writer
+        .write_frame(&frame, 1000, UNIX_EPOCH)
+        .expect("Writing frame failed");
+    let out = writer.w;
+    let packet = &out[60 + 28..];
+    assert_eq!(packet[5], 0);
+    assert_eq!(&packet[16..18], &1u16.to_le_bytes());
+    assert_eq!(
+        &packet[20..20 + 43],
+        b"CRC error, received 0x1234, computed 0x5678"
+    );
+}
@@ -0,0 +1,339 @@
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp, Tag, TagValue};
+use rustradio::{Error, Float};
+use std::collections::VecDeque;
+
+// minimum normalized correlation of the soft symbols with a pattern to accept them as candidate,
+// where 1.0 is a perfect match of symbols of equal magnitude, allowing for few weak symbols of the
+// wrong sign, but not for the strongly varying magnitudes of noise
+const THRESHOLD: Float = 0.75;
+
+// length of the alternating preamble in symbols
+const PREAMBLE_LEN: usize = 8;
+
+// key of the tags marking the start of candidate windows not continuing the previous one
+pub const WINDOW_START: &str = "window_start";
+
+// pattern with its bits packed into an integer, the last bit being the least significant one
+struct Pattern {
+    bits: Vec<bool>,
+    packed: u64,
+    mask: u64,
+    // sign mismatches above which the threshold cannot be reached, even if all mismatching
+    // symbols are negligible
+    max_mismatches: u32,
+}
+
+impl Pattern {
+    fn new(bits: Vec<bool>) -> Result<Self, Error> {
+        if bits.is_empty() || bits.len() > 64 {
+            return Err(Error::new(&format!(
+                "Preamble and address prefix of {} bits exceed the 64 bits of a sync pattern",
+                bits.len()
+            )));
+        }
+        let packed = bits.iter().fold(0, |acc, bit| (acc << 1) | *bit as u64);
+        let mask = u64::MAX >> (64 - bits.len());
+        let max_mismatches = (bits.len() as Float * (1.0 - THRESHOLD * THRESHOLD)) as u32;
+        Ok(Self {
+            bits,
+            packed,
+            mask,
+            max_mismatches,
+        })
+    }
+}
+
+/// Patterns of the preamble followed by the given first bits of the address as transmitted
+///
+/// The preamble alternates such that it ends with the inverse of the first bit of the address. If
+/// no bits of the address are known, the first one is assumed to continue the alternating
+/// preamble, which is then started by either a one or a zero.
+pub fn sync_patterns(prefix: &[bool]) -> Vec<Vec<bool>> {
+    let preamble = |first: bool| (0..PREAMBLE_LEN).map(move |i| (i % 2 == 0) == first);
+    match prefix.first() {
+        Some(first) => vec![preamble(*first).chain(prefix.iter().copied()).collect()],
+        None => [true, false]
+            .iter()
+            .map(|first| preamble(*first).chain([*first]).collect())
+            .collect(),
+    }
+}
+
+/// Indices of the symbols within a read buffer of positions which start a candidate window
+///
+/// Deframers reset at these symbols, as they do not follow the symbols passed on before.
+pub fn window_starts(tags: &[Tag]) -> Vec<usize> {
+    tags.iter()
+        .filter(|tag| tag.key() == WINDOW_START)
+        .map(Tag::pos)
+        .collect()
+}
+
+/// Gate passing only candidate frames from the soft symbols of a symbol synchronizer
+///
+/// The soft symbols are correlated with the patterns of preamble and address prefix of all frames
+/// to receive, so that weak symbols are given less weight than they would be after slicing.
+/// Whenever the correlation exceeds a threshold, the symbols of the pattern are passed on, those of
+/// the preamble with the signs of the pattern, followed by the remaining symbols of the longest
+/// possible frame, which is extended by candidates found within. Symbols of the address are passed
+/// on unchanged, so that the deframer still drops frames of other addresses close to the prefix.
+/// All other symbols are dropped, sparing the deframer from decoding noise. Windows following
+/// dropped symbols are tagged in the stream of positions, see [`window_starts`].
+pub struct PreambleCorrelator {
+    src: Streamp<Float>,
+    // positions of the symbols within the demodulated stream
+    positions: Streamp<u64>,
+    dst: Streamp<Float>,
+    dst_pos: Streamp<u64>,
+    patterns: Vec<Pattern>,
+    // number of symbols passed on per candidate including pattern
+    window_len: usize,
+    // most recent symbols and their positions, as many as the longest pattern
+    history: VecDeque<(Float, u64)>,
+    history_len: usize,
+    // signs of the most recent symbols, the last one being the least significant bit
+    signs: u64,
+    // number of the most recent symbols which have not been passed on
+    dropped: usize,
+    // symbols still to be passed on of the current candidate
+    remaining: usize,
+}
+
+impl PreambleCorrelator {
+    pub fn new(
+        src: Streamp<Float>,
+        positions: Streamp<u64>,
+        patterns: Vec<Vec<bool>>,
+        window_len: usize,
+    ) -> Result<Self, Error> {
+        let history_len = patterns.iter().map(Vec::len).max().unwrap_or(0);
+        assert!(window_len >= history_len);
+        Ok(Self {
+            src,
+            positions,
+            dst: Stream::newp(),
+            dst_pos: Stream::newp(),
+            patterns: patterns
+                .into_iter()
+                .map(Pattern::new)
+                .collect::<Result<_, _>>()?,
+            window_len,
+            history: VecDeque::with_capacity(history_len + 1),
+            history_len,
+            signs: 0,
+            dropped: 0,
+            remaining: 0,
+        })
+    }
+
+    /// Get output streams of candidate symbols and their positions within the demodulated stream.
+    pub fn out(&self) -> (Streamp<Float>, Streamp<u64>) {
+        (self.dst.clone(), self.dst_pos.clone())
+    }
+
+    // index of the pattern matching the most recent symbols, if any
+    fn matching(&self) -> Option<usize> {
+        self.patterns.iter().position(|pattern| {
+            let Some(start) = self.history.len().checked_sub(pattern.bits.len()) else {
+                return false;
+            };
+            // most windows of noise are ruled out by their signs alone
+            if ((self.signs ^ pattern.packed) & pattern.mask).count_ones() > pattern.max_mismatches
+            {
+                return false;
+            }
+            // cosine similarity of symbols and pattern
+            let (correlation, energy) = self
+                .history
+                .range(start..)
+                .zip(pattern.bits.iter())
+                .fold((0.0, 0.0), |(c, e), ((s, _), bit)| {
+                    (c + if *bit { *s } else { -*s }, e + s * s)
+                });
+            let norm = (energy * pattern.bits.len() as Float).sqrt();
+            norm > 0.0 && correlation >= THRESHOLD * norm
+        })
+    }
+}
+
+impl Block for PreambleCorrelator {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ti = self.src.clone();
+        let (input, _tags) = ti.read_buf()?;
+        let tp = self.positions.clone();
+        let (positions, _tags) = tp.read_buf()?;
+        let n = std::cmp::min(input.len(), positions.len());
+        if n == 0 {
+            drop((input, positions));
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        let (to, to_pos) = self.out();
+        let mut o = to.write_buf()?;
+        let mut o_pos = to_pos.write_buf()?;
+        let out_len = std::cmp::min(o.len(), o_pos.len());
+        // a matching pattern is passed on at once
+        if out_len < self.history_len {
+            return Ok(BlockRet::OutputFull);
+        }
+
+        let mut consumed = 0;
+        let mut opos = 0;
+        let mut tags = Vec::new();
+        for (symbol, pos) in input.iter().zip(positions.iter()).take(n) {
+            if opos + self.history_len > out_len {
+                break;
+            }
+            consumed += 1;
+            self.history.push_back((*symbol, *pos));
+            if self.history.len() > self.history_len {
+                self.history.pop_front();
+            }
+            self.signs = (self.signs << 1) | (*symbol > 0.0) as u64;
+            let matching = self.matching().map(|i| &self.patterns[i].bits);
+            if self.remaining > 0 {
+                // symbols already passed on are kept as they are
+                o.slice()[opos] = *symbol;
+                o_pos.slice()[opos] = *pos;
+                opos += 1;
+                let remaining = matching.map_or(0, |pattern| self.window_len - pattern.len());
+                self.remaining = remaining.max(self.remaining - 1);
+                self.dropped = 0;
+            } else if let Some(pattern) = matching {
+                if self.dropped >= pattern.len() {
+                    tags.push(Tag::new(
+                        opos,
+                        WINDOW_START.to_string(),
+                        TagValue::Bool(true),
+                    ));
+                }
+                // symbols of the pattern which have been passed on already are not repeated
+                let start = self.history.len() - pattern.len().min(self.dropped + 1);
+                let skipped = pattern.len() - (self.history.len() - start);
+                for (i, (s, p)) in self.history.range(start..).enumerate() {
+                    o.slice()[opos] = match skipped + i {
+                        i if i < PREAMBLE_LEN && pattern[i] => s.abs(),
+                        i if i < PREAMBLE_LEN => -s.abs(),
+                        _ => *s,
+                    };
+                    o_pos.slice()[opos] = *p;
+                    opos += 1;
+                }
+                self.remaining = self.window_len - pattern.len();
+                self.dropped = 0;
+            } else {
+                self.dropped += 1;
+            }
+        }
+        input.consume(consumed);
+        positions.consume(consumed);
+        o.produce(opos, &[]);
+        o_pos.produce(opos, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for PreambleCorrelator {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&(self.src.clone(), self.positions.clone()), &self.out())
+    }
+}
+
+impl BlockName for PreambleCorrelator {
+    fn block_name(&self) -> &str {
+        "PreambleCorrelator"
+    }
+}
+
+#[test]
+fn test_sync_patterns() {
+    let bits = |s: &str| s.chars().map(|c| c == '1').collect::<Vec<_>>();
+    assert_eq!(sync_patterns(&bits("110")), vec![bits("10101010110")]);
+    assert_eq!(sync_patterns(&bits("0")), vec![bits("010101010")]);
+    assert_eq!(
+        sync_patterns(&[]),
+        vec![bits("101010101"), bits("010101010")]
+    );
+}
+
+#[test]
+fn test_preamble_correlator() {
+    use crate::test_util::stream_of;
+
+    // noise, a preamble with a weak symbol of the wrong sign followed by address 0b110 and two
+    // further symbols, noise, and a preamble with two strong symbols of the wrong sign
+    let symbols = [
+        0.3, -0.2, 0.5, 0.4, -0.6, //
+        1.0, 0.1, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 0.8, -0.7, //
+        -0.4, 0.2, -0.5, //
+        1.0, -1.0, 1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, -1.0, 0.5,
+    ];
+    let pos: Vec<u64> = (0..symbols.len() as u64).map(|i| i * 4).collect();
+    let (src, positions) = (stream_of(&symbols), stream_of(&pos));
+
+    let mut correlator =
+        PreambleCorrelator::new(src, positions, sync_patterns(&[true, true, false]), 13)
+            .expect("Invalid patterns");
+    correlator.work().expect("Correlation failed");
+    let (out, out_pos) = correlator.out();
+    let (out, _) = out.read_buf().expect("Stream not readable");
+    let (out_pos, tags) = out_pos.read_buf().expect("Stream not readable");
+    // the weak symbol is corrected, symbols outside the candidate window are dropped
+    assert_eq!(
+        out.slice(),
+        &[1.0, -0.1, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 0.8, -0.7]
+    );
+    assert_eq!(out_pos.slice(), &pos[5..18]);
+    assert_eq!(window_starts(&tags), vec![0]);
+
+    // patterns are limited to 64 bits
+    let (src, positions) = (stream_of(&symbols), stream_of(&pos));
+    assert!(PreambleCorrelator::new(src, positions, sync_patterns(&[true; 57]), 65).is_err());
+}
+
+#[test]
+fn test_neighbouring_address() {
+    use crate::deframer::{NrfConfig, NrfDeframer, NrfFrame};
+    use crate::test_util::stream_with_positions;
+
+    let config = NrfConfig::fixed_length(40, 5, 2, &[1, 2, 3, 4, 5]).crc_length(0);
+    // frames of the target address and of an address differing in its last bit, which still
+    // correlates with the pattern, preceded by noise
+    let receive = |address: Vec<u8>| {
+        let frame = NrfFrame {
+            address,
+            payload: vec![6, 7],
+            ..Default::default()
+        };
+        let symbols: Vec<Float> = [0.3, -0.2, 0.5, 0.4, -0.6]
+            .into_iter()
+            .chain(
+                frame
+                    .encode_fixed(0)
+                    .iter()
+                    .map(|b| if b { 1.0 } else { -1.0 }),
+            )
+            .collect();
+        let (src, positions) = stream_with_positions(&symbols);
+
+        let mut correlator = PreambleCorrelator::new(
+            src,
+            positions,
+            config.sync_patterns(),
+            config.max_frame_bits(),
+        )
+        .expect("Invalid patterns");
+        correlator.work().expect("Correlation failed");
+        let (candidates, positions) = correlator.out();
+        let mut deframer = NrfDeframer::new(candidates, positions, vec![config.clone()]);
+        deframer.work().expect("Deframing failed");
+        deframer.out().pop().map(|(frame, _)| frame.address)
+    };
+    assert_eq!(receive(vec![1, 2, 3, 4, 5]), Some(vec![1, 2, 3, 4, 5]));
+    assert_eq!(receive(vec![1, 2, 3, 4, 4]), None);
+}
@@ -0,0 +1,340 @@
+use crate::deframer::{checksum, Digest, Framing, NrfFrame, Pcf, StreamTiming};
+use crate::eof::{self, Ends};
+use crate::preamble_correlator;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp, Streamp};
+use rustradio::Error;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+const ADDRESS_LENGTHS: RangeInclusive<usize> = 3..=5;
+const MAX_PAYLOAD_LEN: usize = 32;
+
+// preamble followed by the longest possible frame, an ESB frame with 5 byte address and 32 byte
+// payload, frames are only decoded once the window holds this many bits or the input has ended
+pub const WINDOW_BITS: usize = 8 + (5 + 2 + MAX_PAYLOAD_LEN + 2) * 8 - 7;
+
+// packs bits into bytes, most significant bit first, the last byte is padded with zeros
+fn to_bytes(bits: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(WINDOW_BITS.div_ceil(8) + 1);
+    for (i, bit) in bits.enumerate() {
+        if i % 8 == 0 {
+            bytes.push(0);
+        }
+        *bytes.last_mut().unwrap() |= (bit as u8) << (7 - i % 8);
+    }
+    bytes
+}
+
+/// Deframer of nRF24 frames with unknown address length, payload length and framing
+///
+/// After every preamble, Enhanced ShockBurst framing is tried for all address lengths and
+/// fixed-length framing for all frame lengths, and the shortest candidate passing the CRC check is
+/// accepted. Without PCF, address and payload cannot be told apart, so these frames are split at
+/// the given address length.
+pub struct PromiscuousDeframer {
+    src: Streamp<u8>,
+    // positions of the bits within the demodulated stream
+    positions: Streamp<u64>,
+    dst: NoCopyStreamp<NrfFrame>,
+    channel: u8,
+    // address length assumed for frames without PCF
+    address_len: usize,
+    // frames are accepted if their address starts with any of the prefixes, or if none are given
+    address_prefixes: Vec<Vec<u8>>,
+    // length of CRC checksum in bytes (1-2)
+    crc_len: usize,
+    timing: StreamTiming,
+    // most recent bits and their positions within the demodulated stream
+    window: VecDeque<(bool, u64)>,
+}
+
+impl PromiscuousDeframer {
+    pub fn new(
+        src: Streamp<u8>,
+        positions: Streamp<u64>,
+        channel: u8,
+        address_len: usize,
+        address_prefixes: &[Vec<u8>],
+        crc_len: usize,
+        timing: StreamTiming,
+    ) -> Self {
+        assert!(crc_len > 0);
+        Self {
+            src,
+            positions,
+            dst: NoCopyStream::newp(),
+            channel,
+            address_len,
+            address_prefixes: address_prefixes.to_vec(),
+            crc_len,
+            timing,
+            window: VecDeque::with_capacity(WINDOW_BITS),
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+
+    // decodes the frame starting with a preamble at the start of the window, returns the frame
+    // and its length in bits including preamble, candidates cut short by the end of the window
+    // are skipped
+    fn decode(&self) -> Option<(NrfFrame, usize)> {
+        let bits: Vec<bool> = self.window.iter().map(|(bit, _)| *bit).collect();
+        // preamble alternates and ends with the inverse of the first address bit
+        if bits.len() < 9 || bits[..9].windows(2).any(|w| w[0] == w[1]) {
+            return None;
+        }
+        let bits = &bits[8..];
+        let bytes = to_bytes(bits.iter().copied());
+        // left padded to align PCF and payload of ESB frames
+        let padded = to_bytes(std::iter::repeat_n(false, 7).chain(bits.iter().copied()));
+
+        // received checksum following the given number of bytes
+        let received = |bytes: &[u8], n: usize| {
+            bytes[n..n + self.crc_len]
+                .iter()
+                .fold(0, |acc, b| (acc << 8) | *b as u16)
+        };
+
+        let esb = ADDRESS_LENGTHS.filter_map(|address_len| {
+            if bits.len() < address_len * 8 + 9 {
+                return None;
+            }
+            let pcf = Pcf::from_bits(
+                bits[address_len * 8..address_len * 8 + 9]
+                    .iter()
+                    .fold(0, |acc, bit| (acc << 1) | *bit as u16),
+            );
+            if pcf.length as usize > MAX_PAYLOAD_LEN {
+                return None;
+            }
+            let n = address_len + 2 + pcf.length as usize;
+            if (n + self.crc_len) * 8 - 7 > bits.len() {
+                return None;
+            }
+            let crc = received(&padded, n);
+            (checksum(&padded[..n], self.crc_len, true) == crc).then(|| NrfFrame {
+                address: bytes[..address_len].to_vec(),
+                payload: padded[address_len + 2..n].to_vec(),
+                pcf: Some(pcf),
+                crc: Some(crc as u32),
+                framing: Some(Framing {
+                    address_len,
+                    shockburst: true,
+                }),
+                ..Default::default()
+            })
+        });
+
+        // the checksum of fixed-length frames only depends on their total length, so it is updated
+        // byte by byte for increasing lengths
+        let mut digest = Digest::new(self.crc_len, false);
+        let fixed = (1..=*ADDRESS_LENGTHS.end() + MAX_PAYLOAD_LEN)
+            .take_while(|n| (n + self.crc_len) * 8 <= bits.len())
+            .filter_map(|n| {
+                digest.update(&bytes[n - 1..n]);
+                if n < *ADDRESS_LENGTHS.start() {
+                    return None;
+                }
+                let crc = received(&bytes, n);
+                let address_len = self.address_len.min(n);
+                (digest.checksum() == crc).then(|| NrfFrame {
+                    address: bytes[..address_len].to_vec(),
+                    payload: bytes[address_len..n].to_vec(),
+                    crc: Some(crc as u32),
+                    framing: Some(Framing {
+                        address_len,
+                        shockburst: false,
+                    }),
+                    ..Default::default()
+                })
+            });
+
+        // a candidate ending within the checksum of a shorter frame passes as well if followed by
+        // zeros, as the checksum over a message followed by its checksum is zero
+        let (frame, frame_len) = esb
+            .chain(fixed)
+            .filter(|frame| {
+                self.address_prefixes.is_empty()
+                    || self
+                        .address_prefixes
+                        .iter()
+                        .any(|prefix| frame.address.starts_with(prefix))
+            })
+            .map(|frame| {
+                let header_len = if frame.pcf.is_some() { 9 } else { 0 };
+                // frame length in bits including 8-bit preamble
+                let frame_len =
+                    8 + (frame.address.len() + frame.payload.len() + self.crc_len) * 8 + header_len;
+                (frame, frame_len)
+            })
+            .min_by_key(|(_, frame_len)| *frame_len)?;
+        let sample_start = self.timing.sample_index(self.window[0].1);
+        let sample_count = frame_len as f64 * self.timing.samples_per_symbol;
+        Some((
+            NrfFrame {
+                channel: self.channel,
+                sample_start,
+                sample_count: sample_count as u64,
+                timestamp: self.timing.timestamp(sample_start),
+                ..frame
+            },
+            frame_len,
+        ))
+    }
+
+    // passes on the frame at the start of the window and skips its bits, or else skips one bit
+    fn advance(&mut self) {
+        match self.decode() {
+            Some((frame, len)) => {
+                self.dst.push(frame, &[]);
+                self.window.drain(..len);
+            }
+            None => {
+                self.window.pop_front();
+            }
+        }
+    }
+}
+
+impl Block for PromiscuousDeframer {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ti = self.src.clone();
+        let (input, _tags) = ti.read_buf()?;
+        let tp = self.positions.clone();
+        let (positions, tags) = tp.read_buf()?;
+        let n = std::cmp::min(input.len(), positions.len());
+        if n == 0 {
+            drop((input, positions));
+            if !(self.src.ended() && self.positions.ended()) {
+                return Ok(BlockRet::Noop);
+            }
+            // frames within the last bits are decoded at the end of the input
+            while !self.window.is_empty() {
+                self.advance();
+            }
+            self.dst.end();
+            return Ok(BlockRet::EOF);
+        }
+
+        let window_starts = preamble_correlator::window_starts(&tags);
+        for (i, (bit, pos)) in input.iter().zip(positions.iter()).take(n).enumerate() {
+            // frames within the bits of a candidate window are decoded before the next one starts
+            if window_starts.binary_search(&i).is_ok() {
+                while !self.window.is_empty() {
+                    self.advance();
+                }
+            }
+            self.window.push_back((*bit != 0, *pos));
+            if self.window.len() == WINDOW_BITS {
+                self.advance();
+            }
+        }
+
+        input.consume(n);
+        positions.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockName for PromiscuousDeframer {
+    fn block_name(&self) -> &str {
+        "Promiscuous nRF deframer"
+    }
+}
+
+impl BlockEOF for PromiscuousDeframer {
+    fn eof(&mut self) -> bool {
+        self.window.is_empty()
+            && eof::propagate(&(self.src.clone(), self.positions.clone()), &self.dst)
+    }
+}
+
+#[test]
+fn test_promiscuous() {
+    use crate::test_util::stream_with_positions;
+
+    let esb = NrfFrame {
+        address: vec![1, 2, 3, 4],
+        payload: vec![5, 6, 7],
+        pcf: Some(Pcf {
+            length: 3,
+            pid: 1,
+            no_ack: false,
+        }),
+        ..Default::default()
+    };
+    let fixed = NrfFrame {
+        address: vec![8, 9, 10],
+        payload: vec![11, 12],
+        ..Default::default()
+    };
+    let bits: Vec<u8> = [true, true, false, true]
+        .into_iter()
+        .chain(esb.encode_dynamic(2).iter())
+        .chain(fixed.encode_fixed(2).iter())
+        // longer candidates pass as well if followed by zeros
+        .chain(std::iter::repeat_n(false, WINDOW_BITS))
+        .map(|b| b as u8)
+        .collect();
+    let (src, positions) = stream_with_positions(&bits);
+
+    let mut deframer =
+        PromiscuousDeframer::new(src, positions, 39, 3, &[], 2, StreamTiming::default());
+    deframer.work().expect("Deframing failed");
+
+    let (out, _) = deframer.out().pop().expect("ESB frame not found");
+    assert_eq!(out.address, esb.address);
+    assert_eq!(out.payload, esb.payload);
+    assert_eq!(out.pcf, esb.pcf);
+    assert_eq!(
+        out.framing,
+        Some(Framing {
+            address_len: 4,
+            shockburst: true
+        })
+    );
+    assert_eq!(out.sample_start, 4);
+    assert_eq!(out.sample_count, esb.encode_dynamic(2).len() as u64);
+
+    let (out, _) = deframer.out().pop().expect("Fixed-length frame not found");
+    assert_eq!(out.address, fixed.address);
+    assert_eq!(out.payload, fixed.payload);
+    assert_eq!(out.pcf, None);
+    assert_eq!(
+        out.framing,
+        Some(Framing {
+            address_len: 3,
+            shockburst: false
+        })
+    );
+}
+
+#[test]
+fn test_promiscuous_eof() {
+    use crate::test_util::stream_with_positions;
+
+    let fixed = NrfFrame {
+        address: vec![8, 9, 10],
+        payload: vec![11, 12],
+        ..Default::default()
+    };
+    // frame at the end of the input, shorter than the window
+    let bits: Vec<u8> = fixed.encode_fixed(1).iter().map(|b| b as u8).collect();
+    let (src, positions) = stream_with_positions(&bits);
+    src.set_eof();
+    positions.set_eof();
+
+    let mut deframer =
+        PromiscuousDeframer::new(src, positions, 39, 3, &[], 1, StreamTiming::default());
+    let out = deframer.out();
+    assert_eq!(deframer.work().expect("Deframing failed"), BlockRet::Ok);
+    assert!(out.pop().is_none());
+    assert_eq!(deframer.work().expect("Deframing failed"), BlockRet::EOF);
+    let (frame, _) = out.pop().expect("Frame at end of input not found");
+    assert_eq!(frame.address, fixed.address);
+    assert_eq!(frame.payload, fixed.payload);
+    assert!(out.ended());
+}
@@ -0,0 +1,64 @@
+use crate::eof;
+use crate::sweep_source::Position;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp};
+use rustradio::{Complex, Error};
+
+/// Passes samples on unchanged, counting them as the position within the stream
+///
+/// Sweep sources count their samples themselves, other sources are followed by this block.
+pub struct SampleCounter {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    position: Position,
+}
+
+impl SampleCounter {
+    pub fn new(src: Streamp<Complex>, position: Position) -> Self {
+        Self {
+            src,
+            dst: Stream::newp(),
+            position,
+        }
+    }
+
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+}
+
+impl Block for SampleCounter {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        if input.is_empty() {
+            drop(input);
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::OutputFull);
+        }
+        o.fill_from_slice(&input.slice()[..n]);
+        input.consume(n);
+        o.produce(n, &tags);
+        *self.position.lock().unwrap() += n as u64;
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for SampleCounter {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for SampleCounter {
+    fn block_name(&self) -> &str {
+        "SampleCounter"
+    }
+}
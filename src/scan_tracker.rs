@@ -0,0 +1,111 @@
+use crate::deframer::NrfFrame;
+use crate::eof;
+use crate::sweep_source::Schedule;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp};
+use rustradio::Error;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Assigns frames received while sweeping to the channels tuned at the time of reception
+///
+/// Frames are decoded as if the receiver stayed on a fixed frequency, their channel is moved by the
+/// distance to the frequency tuned when they were received. Frames on
+/// channels not selected for scanning are dropped, the others are counted per channel.
+pub struct ScanTracker {
+    src: NoCopyStreamp<NrfFrame>,
+    dst: NoCopyStreamp<NrfFrame>,
+    // lowest channel frames are decoded as
+    base: u8,
+    channels: Vec<u8>,
+    schedule: Schedule,
+    // number of frames received per channel
+    counts: Arc<Mutex<BTreeMap<u8, usize>>>,
+}
+
+impl ScanTracker {
+    pub fn new(
+        src: NoCopyStreamp<NrfFrame>,
+        base: u8,
+        channels: Vec<u8>,
+        schedule: Schedule,
+        counts: Arc<Mutex<BTreeMap<u8, usize>>>,
+    ) -> Self {
+        Self {
+            src,
+            dst: NoCopyStream::newp(),
+            base,
+            channels,
+            schedule,
+            counts,
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+
+    // channel the frame was received on, according to the frequency tuned at its start
+    fn channel(&self, frame: &NrfFrame) -> u8 {
+        let schedule = self.schedule.lock().unwrap();
+        let retune = schedule.partition_point(|(start, _)| *start <= frame.sample_start);
+        let base = retune.checked_sub(1).map_or(self.base, |r| schedule[r].1);
+        frame.channel - self.base + base
+    }
+}
+
+impl Block for ScanTracker {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (mut v, tags) = match self.src.pop() {
+            None if self.eof() => return Ok(BlockRet::EOF),
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+
+        v.channel = self.channel(&v);
+        if self.channels.contains(&v.channel) {
+            *self.counts.lock().unwrap().entry(v.channel).or_default() += 1;
+            self.dst.push(v, &tags);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for ScanTracker {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for ScanTracker {
+    fn block_name(&self) -> &str {
+        "ScanTracker"
+    }
+}
+
+#[test]
+fn test_scan_tracker() {
+    let src = NoCopyStream::newp();
+    // decoded on channel 12, received on channels 12, 32 and 22
+    for sample_start in [50, 1500, 2500] {
+        src.push(
+            NrfFrame {
+                channel: 12,
+                sample_start,
+                ..Default::default()
+            },
+            &[],
+        );
+    }
+    let schedule = Arc::new(Mutex::new(vec![(0, 10), (1000, 30), (2000, 20)]));
+    let counts = Arc::new(Mutex::new(BTreeMap::new()));
+    let mut tracker = ScanTracker::new(src, 10, vec![12, 32], schedule, counts.clone());
+    while let BlockRet::Ok = tracker.work().expect("Tracking failed") {}
+
+    let out = tracker.out();
+    assert_eq!(out.pop().expect("Frame dropped").0.channel, 12);
+    assert_eq!(out.pop().expect("Frame dropped").0.channel, 32);
+    // channel 22 is not scanned
+    assert!(out.pop().is_none());
+    assert_eq!(*counts.lock().unwrap(), BTreeMap::from([(12, 1), (32, 1)]));
+}
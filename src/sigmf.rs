@@ -0,0 +1,221 @@
+use crate::iq_decode::SampleFormat;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const VERSION: &str = "1.0.0";
+
+/// Global object of SigMF metadata
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Global {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(rename = "core:sample_rate", skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    #[serde(rename = "core:version")]
+    pub version: String,
+    #[serde(rename = "core:recorder", skip_serializing_if = "Option::is_none")]
+    pub recorder: Option<String>,
+    #[serde(rename = "core:hw", skip_serializing_if = "Option::is_none")]
+    pub hw: Option<String>,
+    // fields not interpreted by this tool, preserved when rewriting metadata
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Capture segment of SigMF metadata
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Capture {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:frequency", skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    #[serde(rename = "core:datetime", skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Annotation segment of SigMF metadata
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Annotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(rename = "core:sample_count", skip_serializing_if = "Option::is_none")]
+    pub sample_count: Option<u64>,
+    #[serde(rename = "core:generator", skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    #[serde(rename = "core:label", skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(rename = "core:comment", skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(
+        rename = "core:freq_lower_edge",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub freq_lower_edge: Option<f64>,
+    #[serde(
+        rename = "core:freq_upper_edge",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub freq_upper_edge: Option<f64>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// SigMF metadata as stored in `.sigmf-meta` files
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Meta {
+    pub global: Global,
+    #[serde(default)]
+    pub captures: Vec<Capture>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+impl Meta {
+    /// Creates metadata for a new recording of complex float samples started now
+    pub fn recording(sample_rate: f64, frequency: f64, hw: Option<String>) -> Self {
+        Meta {
+            global: Global {
+                datatype: "cf32_le".to_string(),
+                sample_rate: Some(sample_rate),
+                version: VERSION.to_string(),
+                recorder: Some(env!("CARGO_PKG_NAME").to_string()),
+                hw,
+                ..Default::default()
+            },
+            captures: vec![Capture {
+                sample_start: 0,
+                frequency: Some(frequency),
+                datetime: Some(iso8601(SystemTime::now())),
+                ..Default::default()
+            }],
+            annotations: vec![],
+        }
+    }
+
+    pub fn load(base: &str) -> Result<Self> {
+        let file = std::fs::File::open(meta_path(base))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save(&self, base: &str) -> Result<()> {
+        let file = std::fs::File::create(meta_path(base))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn sample_format(&self) -> Result<SampleFormat> {
+        match self.global.datatype.as_str() {
+            "cf32_le" => Ok(SampleFormat::Cf32),
+            "ci8" => Ok(SampleFormat::Cs8),
+            "ci16_le" => Ok(SampleFormat::Cs16),
+            t => Err(anyhow!("Unsupported SigMF data type: {}", t)),
+        }
+    }
+
+    // center frequency of the first capture segment
+    pub fn frequency(&self) -> Option<f64> {
+        self.captures.first().and_then(|c| c.frequency)
+    }
+
+    // start time of the first capture segment
+    pub fn datetime(&self) -> Option<SystemTime> {
+        self.captures
+            .first()
+            .and_then(|c| c.datetime.as_deref())
+            .and_then(parse_iso8601)
+    }
+}
+
+// returns base name of a SigMF recording if the path refers to one
+pub fn base_name(path: &str) -> Option<&str> {
+    [".sigmf-meta", ".sigmf-data", ".sigmf"]
+        .iter()
+        .find_map(|ext| path.strip_suffix(ext))
+}
+
+pub fn meta_path(base: &str) -> String {
+    format!("{}.sigmf-meta", base)
+}
+
+pub fn data_path(base: &str) -> String {
+    format!("{}.sigmf-data", base)
+}
+
+// formats time as ISO 8601 UTC timestamp with millisecond precision
+pub fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // convert days since epoch to civil date (proleptic Gregorian calendar)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// parses ISO 8601 UTC timestamp as written by SigMF, e.g. 2024-02-29T23:59:59.123Z
+pub fn parse_iso8601(s: &str) -> Option<SystemTime> {
+    let (date, time) = s.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|x| x.parse::<i64>());
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (hms, frac) = time.split_once('.').unwrap_or((time, "0"));
+    let mut hms = hms.splitn(3, ':').map(|x| x.parse::<u64>());
+    let (hour, min, sec) = (hms.next()?.ok()?, hms.next()?.ok()?, hms.next()?.ok()?);
+    let nanos = format!("{:0<9}", frac).get(..9)?.parse::<u32>().ok()?;
+
+    // convert civil date (proleptic Gregorian calendar) to days since epoch
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
+
+    let secs = days * 86400 + hour * 3600 + min * 60 + sec;
+    Some(UNIX_EPOCH + std::time::Duration::new(secs, nanos))
+}
+
+#[test]
+fn test_iso8601() {
+    let time = UNIX_EPOCH + std::time::Duration::from_millis(1_709_251_199_123);
+    assert_eq!(iso8601(time), "2024-02-29T23:59:59.123Z");
+    assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    assert_eq!(parse_iso8601("2024-02-29T23:59:59.123Z"), Some(time));
+    assert_eq!(parse_iso8601("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+    assert_eq!(parse_iso8601("2024-02-29 23:59:59"), None);
+}
+
+#[test]
+fn test_roundtrip() {
+    let json = r#"{"global":{"core:datatype":"ci8","core:sample_rate":8000000.0,"core:version":"1.0.0","core:author":"someone"},"captures":[{"core:sample_start":0,"core:frequency":2440000000.0}]}"#;
+    let meta: Meta = serde_json::from_str(json).expect("Parsing failed");
+    assert_eq!(
+        meta.sample_format().expect("Unknown format"),
+        SampleFormat::Cs8
+    );
+    assert_eq!(meta.frequency(), Some(2_440_000_000.0));
+    let out = serde_json::to_string(&meta).expect("Serialization failed");
+    assert!(out.contains(r#""core:author":"someone""#));
+}
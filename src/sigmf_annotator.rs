@@ -0,0 +1,109 @@
+use crate::deframer::{channel_freq, NrfFrame};
+use crate::eof;
+use crate::sigmf::Annotation;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp};
+use rustradio::Error;
+use std::sync::{Arc, Mutex};
+
+/// Collects SigMF annotations for all passing frames
+pub struct SigMFAnnotator {
+    src: NoCopyStreamp<NrfFrame>,
+    dst: NoCopyStreamp<NrfFrame>,
+    // occupied bandwidth of an nRF channel and of a BLE channel in Hz
+    bandwidths: (f32, f32),
+    annotations: Arc<Mutex<Vec<Annotation>>>,
+}
+
+impl SigMFAnnotator {
+    pub fn new(
+        src: NoCopyStreamp<NrfFrame>,
+        bandwidths: (f32, f32),
+        annotations: Arc<Mutex<Vec<Annotation>>>,
+    ) -> Self {
+        Self {
+            src,
+            dst: NoCopyStream::newp(),
+            bandwidths,
+            annotations,
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+}
+
+// PCF or BLE PDU type, and received and computed checksum of frames failing the CRC check
+fn comment(frame: &NrfFrame) -> Option<String> {
+    let header = frame
+        .pcf
+        .map(|pcf| format!("PID {}, NO_ACK {}", pcf.pid, pcf.no_ack as u8))
+        .or_else(|| frame.ble.map(|ble| ble.pdu_type_name().to_string()));
+    let crc_error = frame
+        .crc
+        .zip(frame.computed_crc)
+        .map(|(crc, computed_crc)| {
+            format!(
+                "CRC error, received {:#x}, computed {:#x}",
+                crc, computed_crc
+            )
+        });
+    match (header, crc_error) {
+        (Some(header), Some(crc_error)) => Some(format!("{}, {}", header, crc_error)),
+        (header, crc_error) => header.or(crc_error),
+    }
+}
+
+// annotation of a frame occupying the given bandwidth around its channel frequency
+pub fn annotation(frame: &NrfFrame, bandwidth: f32) -> Annotation {
+    let freq = channel_freq(frame.channel);
+    Annotation {
+        sample_start: frame.sample_start,
+        sample_count: Some(frame.sample_count),
+        generator: Some(env!("CARGO_PKG_NAME").to_string()),
+        label: Some(format!(
+            "{} {}",
+            hex::encode(&frame.address),
+            hex::encode(&frame.payload)
+        )),
+        comment: comment(frame),
+        freq_lower_edge: Some(freq - bandwidth as f64 / 2.0),
+        freq_upper_edge: Some(freq + bandwidth as f64 / 2.0),
+        ..Default::default()
+    }
+}
+
+impl Block for SigMFAnnotator {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (v, tags) = match self.src.pop() {
+            None if self.eof() => return Ok(BlockRet::EOF),
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+
+        let bandwidth = match v.ble {
+            Some(_) => self.bandwidths.1,
+            None => self.bandwidths.0,
+        };
+        self.annotations
+            .lock()
+            .unwrap()
+            .push(annotation(&v, bandwidth));
+
+        self.dst.push(v, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for SigMFAnnotator {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.dst)
+    }
+}
+
+impl BlockName for SigMFAnnotator {
+    fn block_name(&self) -> &str {
+        "SigMFAnnotator"
+    }
+}
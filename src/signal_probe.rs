@@ -0,0 +1,202 @@
+use crate::deframer::{NrfFrame, Signal};
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{NoCopyStream, NoCopyStreamp, Streamp};
+use rustradio::{Complex, Error};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// number of samples averaged for a single noise floor measurement
+const NOISE_WINDOW: usize = 256;
+
+// relative increase of the noise floor per window if the measured power is above it
+const NOISE_RISE: f32 = 0.001;
+
+/// Measures power, SNR and carrier frequency offset of frames from the filtered channel samples
+pub struct SignalProbe {
+    src: NoCopyStreamp<NrfFrame>,
+    samples: Streamp<Complex>,
+    dst: NoCopyStreamp<NrfFrame>,
+    // sample rate of the filtered samples
+    sample_rate: f32,
+    // delay of the filtered samples relative to the sample positions of frames
+    delay: u64,
+    // number of input samples per filtered sample
+    decimation: u64,
+    history: VecDeque<Complex>,
+    // number of recent samples kept to measure frames
+    history_len: usize,
+    // number of samples received so far
+    position: u64,
+    // estimated noise power, tracks the quietest windows
+    noise_floor: Option<f32>,
+    // accumulated power of the current noise window
+    window_power: f32,
+    window_len: usize,
+}
+
+impl SignalProbe {
+    pub fn new(
+        src: NoCopyStreamp<NrfFrame>,
+        samples: Streamp<Complex>,
+        sample_rate: f32,
+        delay: u64,
+        decimation: u64,
+        frame_len: usize,
+    ) -> Self {
+        // samples are read as soon as they are passed on to demodulation, which runs behind by at
+        // most the samples buffered for it, while the blocks after it pass on frames once all of
+        // their samples have been demodulated
+        let history_len = samples.total_size() + frame_len;
+        Self {
+            src,
+            samples,
+            dst: NoCopyStream::newp(),
+            sample_rate,
+            delay,
+            decimation,
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            position: 0,
+            noise_floor: None,
+            window_power: 0.0,
+            window_len: 0,
+        }
+    }
+
+    pub fn out(&self) -> NoCopyStreamp<NrfFrame> {
+        self.dst.clone()
+    }
+
+    fn push_sample(&mut self, sample: Complex) {
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+        self.position += 1;
+
+        self.window_power += sample.norm_sqr();
+        self.window_len += 1;
+        if self.window_len == NOISE_WINDOW {
+            let power = self.window_power / NOISE_WINDOW as f32;
+            self.noise_floor = Some(match self.noise_floor {
+                Some(floor) if power > floor => (floor * (1.0 + NOISE_RISE)).min(power),
+                _ => power,
+            });
+            self.window_power = 0.0;
+            self.window_len = 0;
+        }
+    }
+
+    // measures the signal within the samples of a frame which are still in the history
+    fn measure(&self, frame: &NrfFrame) -> Option<Signal> {
+        let history_start = self.position - self.history.len() as u64;
+        let start = (frame.sample_start + self.delay) / self.decimation;
+        let end = (frame.sample_start + self.delay + frame.sample_count) / self.decimation;
+        let (start, end) = (start.max(history_start), end.min(self.position));
+        if end <= start + 1 {
+            return None;
+        }
+        let samples = self
+            .history
+            .range((start - history_start) as usize..(end - history_start) as usize);
+
+        let power = samples.clone().map(|s| s.norm_sqr()).sum::<f32>() / (end - start) as f32;
+
+        // mean instantaneous frequency above and below the carrier, the offset is centered
+        // between both to be independent of the ratio of ones and zeros
+        let (mut upper, mut lower) = ((0.0, 0), (0.0, 0));
+        for (a, b) in samples.clone().zip(samples.skip(1)) {
+            let freq = (b * a.conj()).arg();
+            let sum = if freq > 0.0 { &mut upper } else { &mut lower };
+            sum.0 += freq;
+            sum.1 += 1;
+        }
+        let offset = match (upper, lower) {
+            ((u, un), (l, ln)) if un > 0 && ln > 0 => (u / un as f32 + l / ln as f32) / 2.0,
+            ((u, un), (l, ln)) => (u + l) / (un + ln) as f32,
+        };
+
+        Some(Signal {
+            power: 10.0 * power.log10(),
+            snr: self
+                .noise_floor
+                .map_or(0.0, |floor| 10.0 * (power / floor).log10()),
+            freq_offset: offset * self.sample_rate / (2.0 * PI),
+        })
+    }
+}
+
+impl Block for SignalProbe {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        // frames are taken before the samples, as their samples are passed on before demodulation
+        let frames: Vec<_> = std::iter::from_fn(|| self.src.pop()).collect();
+
+        let samples = self.samples.clone();
+        let (input, _tags) = samples.read_buf()?;
+        let n = input.len();
+        input.iter().for_each(|s| self.push_sample(*s));
+        input.consume(n);
+
+        if frames.is_empty() && n == 0 {
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        for (mut v, tags) in frames {
+            v.signal = self.measure(&v);
+            self.dst.push(v, &tags);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for SignalProbe {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&(self.src.clone(), self.samples.clone()), &self.dst)
+    }
+}
+
+impl BlockName for SignalProbe {
+    fn block_name(&self) -> &str {
+        "SignalProbe"
+    }
+}
+
+#[test]
+fn test_signal_probe() {
+    use crate::test_util::stream_of;
+
+    let sample_rate = 1_000_000.0;
+    let noise = (0..1024).map(|i| Complex::new(0.001 * (i % 3) as f32, 0.0));
+    // 2-FSK at 10 kHz offset with deviation of 100 kHz and unbalanced symbols
+    let mut phase = 0.0f32;
+    let packet = (0..400).map(|i| {
+        let dev = if i % 40 < 30 { 100_000.0 } else { -100_000.0 };
+        phase += 2.0 * PI * (10_000.0 + dev) / sample_rate;
+        Complex::from_polar(0.5, phase)
+    });
+    let samples: Vec<Complex> = noise.chain(packet).collect();
+
+    let src = NoCopyStream::newp();
+    src.push(
+        NrfFrame {
+            sample_start: 1024 - 8,
+            sample_count: 400,
+            ..Default::default()
+        },
+        &[],
+    );
+    let stream = stream_of(&samples);
+
+    let mut probe = SignalProbe::new(src, stream, sample_rate, 8, 1, 400);
+    probe.work().expect("Measurement failed");
+
+    let (frame, _) = probe.out().pop().expect("Frame not passed on");
+    let signal = frame.signal.expect("Signal not measured");
+    assert!((signal.power - 10.0 * 0.25f32.log10()).abs() < 0.01);
+    assert!(signal.snr > 40.0);
+    assert!((signal.freq_offset - 10_000.0).abs() < 100.0);
+}
@@ -0,0 +1,184 @@
+use crate::deframer::channel_freq;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp};
+use rustradio::{Complex, Error};
+use soapysdr::{Device, Direction, ErrorCode, RxStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// time until the receiver has settled after retuning, samples received meanwhile are discarded
+const SETTLE_TIME: Duration = Duration::from_millis(2);
+
+/// Position within the stream at which the source was tuned, and the lowest channel covered from
+/// then on, for every retune
+pub type Schedule = Arc<Mutex<Vec<(u64, u8)>>>;
+
+/// Number of samples emitted by a sweep source so far
+pub type Position = Arc<Mutex<u64>>;
+
+/// Frequency requested from a sweep source
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Tuning {
+    // cycle through all steps of the sweep
+    #[default]
+    Sweep,
+    // stay on the frequency covering the channels starting from the given lowest one
+    Hold(u8),
+}
+
+/// SoapySDR source sweeping across the band in steps of adjacent channels
+///
+/// The receiver dwells on every step for a fixed time, unless it is held on a frequency by
+/// another block. The time spent retuning is filled with zeros, so that positions within the
+/// stream still match the time of reception.
+pub struct SweepSource {
+    dev: Device,
+    stream: RxStream<Complex>,
+    dst: Streamp<Complex>,
+    sample_rate: f64,
+    // lowest channel of every step
+    bases: Vec<u8>,
+    // number of channels covered by a frequency above its lowest one
+    width: u8,
+    // index of the current step while sweeping
+    step: usize,
+    // lowest channel currently covered
+    base: u8,
+    // number of samples to dwell on every frequency
+    dwell: u64,
+    // samples emitted since the last retune
+    emitted: u64,
+    position: Position,
+    // zeros still to emit for the time spent retuning
+    gap: u64,
+    // samples still to discard until the receiver has settled
+    settle: u64,
+    schedule: Schedule,
+    tuning: Arc<Mutex<Tuning>>,
+}
+
+impl SweepSource {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        driver: &str,
+        bases: Vec<u8>,
+        width: u8,
+        sample_rate: f64,
+        gain: f64,
+        dwell: Duration,
+        schedule: Schedule,
+        tuning: Arc<Mutex<Tuning>>,
+        position: Position,
+    ) -> Result<Self, Error> {
+        assert!(!bases.is_empty());
+        let dev = Device::new(driver)?;
+        dev.set_frequency(Direction::Rx, 0, center_freq(bases[0], width), ())?;
+        dev.set_sample_rate(Direction::Rx, 0, sample_rate)?;
+        dev.set_gain(Direction::Rx, 0, gain)?;
+        let mut stream = dev.rx_stream(&[0])?;
+        stream.activate(None)?;
+        schedule.lock().unwrap().push((0, bases[0]));
+        Ok(Self {
+            dev,
+            stream,
+            dst: Stream::newp(),
+            sample_rate,
+            base: bases[0],
+            bases,
+            width,
+            step: 0,
+            dwell: ((dwell.as_secs_f64() * sample_rate) as u64).max(1),
+            emitted: 0,
+            position,
+            gap: 0,
+            settle: 0,
+            schedule,
+            tuning,
+        })
+    }
+
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    // tunes to the frequency covering the channels starting from the given lowest one, the
+    // stream is paused meanwhile to drop samples in flight
+    fn retune(&mut self, base: u8) -> Result<(), Error> {
+        let start = Instant::now();
+        self.stream.deactivate(None)?;
+        self.dev
+            .set_frequency(Direction::Rx, 0, center_freq(base, self.width), ())?;
+        self.stream.activate(None)?;
+        self.base = base;
+
+        self.gap = (start.elapsed().as_secs_f64() * self.sample_rate) as u64;
+        self.settle = (SETTLE_TIME.as_secs_f64() * self.sample_rate) as u64;
+        self.emitted = 0;
+        let position = *self.position.lock().unwrap();
+        self.schedule.lock().unwrap().push((position, base));
+        Ok(())
+    }
+}
+
+// center frequency covering the channels starting from the lowest one
+pub fn center_freq(base: u8, width: u8) -> f64 {
+    channel_freq(base) + 500_000.0 * width as f64
+}
+
+impl Block for SweepSource {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let tuning = *self.tuning.lock().unwrap();
+        let sweeping = tuning == Tuning::Sweep && self.bases.len() > 1;
+        match tuning {
+            Tuning::Hold(base) if base != self.base => self.retune(base)?,
+            Tuning::Sweep if sweeping && self.emitted >= self.dwell => {
+                self.step = (self.step + 1) % self.bases.len();
+                self.retune(self.bases[self.step])?;
+            }
+            _ => {}
+        }
+        let mut o = self.dst.write_buf()?;
+        let remaining = if sweeping {
+            self.dwell - self.emitted
+        } else {
+            u64::MAX
+        };
+        let space = std::cmp::min(o.len() as u64, remaining);
+        if space == 0 {
+            return Ok(BlockRet::OutputFull);
+        }
+
+        let n = if self.gap > 0 {
+            let n = std::cmp::min(space, self.gap) as usize;
+            o.slice()[..n].fill(Complex::default());
+            self.gap -= n as u64;
+            n
+        } else {
+            let timeout_us = 10_000;
+            let n = match self
+                .stream
+                .read(&mut [&mut o.slice()[..space as usize]], timeout_us)
+            {
+                Ok(n) => n,
+                Err(e) if e.code == ErrorCode::Timeout => return Ok(BlockRet::Ok),
+                Err(e) => return Err(e.into()),
+            };
+            let discard = std::cmp::min(n as u64, self.settle) as usize;
+            o.slice()[..discard].fill(Complex::default());
+            self.settle -= discard as u64;
+            n
+        };
+        o.produce(n, &[]);
+        self.emitted += n as u64;
+        *self.position.lock().unwrap() += n as u64;
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for SweepSource {}
+
+impl BlockName for SweepSource {
+    fn block_name(&self) -> &str {
+        "SweepSource"
+    }
+}
@@ -0,0 +1,254 @@
+use crate::eof;
+use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
+use rustradio::stream::{Stream, Streamp};
+use rustradio::{Error, Float};
+use std::collections::VecDeque;
+
+// proportional gain of the timing loop in symbols per unit of timing error
+const TIMING_GAIN: f64 = 0.25;
+// integral gain of the timing loop, adapting the symbol period to the clock of the transmitter
+const PERIOD_GAIN: f64 = TIMING_GAIN * TIMING_GAIN / 16.0;
+// maximum deviation of the symbol period from its nominal value
+const MAX_DRIFT: f64 = 0.005;
+// length of the alternating preamble in symbols
+const PREAMBLE_LEN: usize = 8;
+
+/// Symbol synchronizer for GFSK, recovering symbol timing and frequency offset from the output of
+/// a quadrature demodulator
+///
+/// Symbols are interpolated at the instants given by a Gardner timing error detector, whose loop
+/// follows drifting symbol rates of transmitters with inaccurate crystals. A frequency offset
+/// shifts the demodulated signal, which is measured as the mean of the alternating symbols of the
+/// preamble and subtracted from all following symbols, so that symbols can be sliced at zero. In
+/// addition to the symbols, the position of each symbol within the input stream is emitted.
+pub struct SymbolSync {
+    src: Streamp<Float>,
+    dst: Streamp<Float>,
+    dst_pos: Streamp<u64>,
+    // nominal and current number of samples per symbol
+    sps: f64,
+    period: f64,
+    // most recent samples, the first one at position `first`
+    history: VecDeque<Float>,
+    first: u64,
+    // position of the next symbol within the input stream
+    next: f64,
+    // most recent symbols before compensation of the frequency offset
+    window: VecDeque<Float>,
+    // frequency offset in units of the demodulated signal
+    offset: Float,
+}
+
+impl SymbolSync {
+    pub fn new(src: Streamp<Float>, sps: f64) -> Self {
+        assert!(sps >= 2.0);
+        Self {
+            src,
+            dst: Stream::newp(),
+            dst_pos: Stream::newp(),
+            sps,
+            period: sps,
+            history: VecDeque::with_capacity((4.0 * sps) as usize + 3),
+            first: 0,
+            next: sps,
+            window: VecDeque::with_capacity(PREAMBLE_LEN + 1),
+            offset: 0.0,
+        }
+    }
+
+    /// Get output streams of symbols and their positions within the input stream.
+    pub fn out(&self) -> (Streamp<Float>, Streamp<u64>) {
+        (self.dst.clone(), self.dst_pos.clone())
+    }
+
+    // linearly interpolated sample at the given position, which must be within the history
+    fn interpolate(&self, pos: f64) -> Float {
+        let i = (pos - self.first as f64).max(0.0);
+        let mu = i.fract() as Float;
+        let a = self.history[i as usize];
+        let b = self.history.get(i as usize + 1).copied().unwrap_or(a);
+        a + (b - a) * mu
+    }
+
+    // frequency offset measured on the most recent symbols if they look like a preamble, i.e.
+    // they alternate around their mean and both levels are consistent
+    fn preamble_offset(&self) -> Option<Float> {
+        if self.window.len() < PREAMBLE_LEN {
+            return None;
+        }
+        let mean = self.window.iter().sum::<Float>() / PREAMBLE_LEN as Float;
+        let alternating = self
+            .window
+            .iter()
+            .zip(self.window.iter().skip(1))
+            .all(|(a, b)| (*a > mean) != (*b > mean));
+        let spread = |high: bool| {
+            let levels = self.window.iter().filter(|s| (**s > mean) == high);
+            let (min, max) = levels.fold((Float::MAX, Float::MIN), |(min, max), s| {
+                (min.min(*s), max.max(*s))
+            });
+            max - min
+        };
+        let step =
+            self.window.iter().map(|s| (s - mean).abs()).sum::<Float>() / PREAMBLE_LEN as Float;
+        (alternating && spread(true) < step / 2.0 && spread(false) < step / 2.0).then_some(mean)
+    }
+
+    // if the symbol at the current timing and the following two are sampled at the transitions
+    // of an alternating preamble, i.e. about half a symbol off, returns the correction of the
+    // timing in symbols towards the peak of the same sign as the symbol
+    fn at_transitions(&self) -> Option<f64> {
+        let at = |symbols: f64| self.interpolate(self.next + symbols * self.period);
+        let peaks = [at(-0.5), at(0.5), at(1.5), at(2.5)];
+        let (mean, step) = ((peaks[0] + peaks[1]) / 2.0, (peaks[0] - peaks[1]).abs());
+        // peaks of opposite sign, unless shifted by a large frequency offset, alternating between
+        // two levels, so that the transitions of runs of equal bits within a frame don't match
+        let alternating = step > (peaks[0].abs() + peaks[1].abs()) / 2.0
+            && (peaks[0] - peaks[2]).abs() < step / 4.0
+            && (peaks[1] - peaks[3]).abs() < step / 4.0;
+        let transitions = alternating && (0..3).all(|n| (at(n as f64) - mean).abs() < step / 4.0);
+        let late = (at(0.0) > mean) == (peaks[0] > peaks[1]);
+        transitions.then_some(if late { -0.5 } else { 0.5 })
+    }
+
+    // samples the symbol at the current timing and updates timing and frequency offset, returns
+    // the symbol after compensation of the frequency offset and its position
+    fn symbol(&mut self) -> (Float, u64) {
+        // at the start of a frame, the timing left by noise may be half a symbol off, where the
+        // timing error detector can hardly tell early from late for most of the preamble, so the
+        // timing is moved to the adjacent peak instead
+        let correction = self.at_transitions();
+        if let Some(symbols) = correction {
+            self.next += symbols * self.period;
+        }
+        let pos = self.next.round() as u64;
+        let raw = self.interpolate(self.next);
+        let middle = self.interpolate(self.next - self.period / 2.0);
+        let last = self.window.back().copied().unwrap_or(raw);
+
+        // Gardner timing error at transitions, positive if sampling late, relative to the midpoint
+        // and normalized by the step between the symbols, so that it approximates twice the
+        // timing error in symbols regardless of frequency offset and amplitude
+        let error = if correction.is_some() {
+            0.0
+        } else if (raw > self.offset) != (last > self.offset) {
+            (2.0 * (middle - (raw + last) / 2.0) / (raw - last)).clamp(-1.0, 1.0) as f64
+        } else {
+            0.0
+        };
+        self.period = (self.period + PERIOD_GAIN * error * self.sps)
+            .clamp(self.sps * (1.0 - MAX_DRIFT), self.sps * (1.0 + MAX_DRIFT));
+        self.next += self.period - TIMING_GAIN * error * self.sps;
+
+        self.window.push_back(raw);
+        if self.window.len() > PREAMBLE_LEN {
+            self.window.pop_front();
+        }
+        if let Some(offset) = self.preamble_offset() {
+            self.offset = offset;
+        }
+        (raw - self.offset, pos)
+    }
+}
+
+impl Block for SymbolSync {
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ti = self.src.clone();
+        let (input, _tags) = ti.read_buf()?;
+        if input.is_empty() {
+            drop(input);
+            return Ok(if self.eof() {
+                BlockRet::EOF
+            } else {
+                BlockRet::Noop
+            });
+        }
+        let (to, to_pos) = self.out();
+        let mut o = to.write_buf()?;
+        let mut o_pos = to_pos.write_buf()?;
+        let out_len = std::cmp::min(o.len(), o_pos.len());
+        if out_len == 0 {
+            return Ok(BlockRet::OutputFull);
+        }
+        let mut n = 0;
+        let mut opos = 0;
+        for sample in input.iter() {
+            n += 1;
+            self.history.push_back(*sample);
+            let last = self.first + self.history.len() as u64 - 1;
+            // samples up to the peak after the next two symbols are required to detect transitions
+            while (self.next + 2.5 * self.period).ceil() <= last as f64 && opos < out_len {
+                let (symbol, pos) = self.symbol();
+                o.slice()[opos] = symbol;
+                o_pos.slice()[opos] = pos;
+                opos += 1;
+            }
+            // keep the samples required for the middle between the next symbol and the last one
+            while self.history.len() > 1 && ((self.first + 1) as f64) < self.next - self.period {
+                self.history.pop_front();
+                self.first += 1;
+            }
+            if opos == out_len {
+                break;
+            }
+        }
+        input.consume(n);
+        o.produce(opos, &[]);
+        o_pos.produce(opos, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+impl BlockEOF for SymbolSync {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&self.src, &self.out())
+    }
+}
+
+impl BlockName for SymbolSync {
+    fn block_name(&self) -> &str {
+        "SymbolSync"
+    }
+}
+
+#[test]
+fn test_symbol_sync() {
+    use crate::modulator::Modulator;
+    use crate::test_util::stream_of;
+
+    // alternating preamble followed by data, demodulated from a GFSK signal with frequency offset
+    // and a symbol rate slightly faster than nominal
+    let bits: Vec<bool> = [0xaa, 0xe7, 0x3c, 0x0f, 0xff, 0x00, 0xa5, 0x5a]
+        .iter()
+        .cycle()
+        .take(64)
+        .flat_map(|b: &u8| (0..8).rev().map(move |i| b >> i & 1 == 1))
+        .collect();
+    let modulated = Modulator::new(4e6, 1e6, 160e3)
+        .drift(2000.0)
+        .modulate(&bits, 60e3);
+    let samples: Vec<Float> = modulated
+        .windows(2)
+        .map(|w| (w[1] * w[0].conj()).arg())
+        .collect();
+    let src = stream_of(&samples);
+
+    let mut sync = SymbolSync::new(src, 4.0);
+    sync.work().expect("Symbol synchronization failed");
+    let (symbols, positions) = sync.out();
+    let (symbols, _) = symbols.read_buf().expect("Stream not readable");
+    let (positions, _) = positions.read_buf().expect("Stream not readable");
+    let received: Vec<bool> = symbols.slice().iter().map(|s| *s > 0.0).collect();
+    // once the preamble has been received, all bits are received without error, also at the end
+    // of the signal where the symbol rate error amounts to several samples
+    let start = received
+        .windows(64)
+        .position(|w| w == &bits[64..128])
+        .expect("Bits not received");
+    assert!(start <= 64 + 8, "{}", start);
+    assert_eq!(&received[start..], &bits[64..64 + received.len() - start]);
+    // symbols are sampled in their center
+    let last = positions.slice()[received.len() - 1] as f64;
+    let expected = (received.len() - start + 64 - 1) as f64 * 4.0 / 1.002 + 1.5;
+    assert!((last - expected).abs() <= 1.0, "{} {}", last, expected);
+}
@@ -0,0 +1,143 @@
+use crate::deframer::NrfConfig;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+
+/// Device to receive, identified by its address or address prefix, and the framing it uses
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Target {
+    // address or address prefix, hexadecimal in the targets file
+    #[serde(deserialize_with = "from_hex")]
+    pub address: Vec<u8>,
+    #[serde(default = "default_address_length")]
+    pub address_length: usize,
+    // fixed payload length, or maximum payload length of Enhanced ShockBurst packets
+    pub payload_length: Option<usize>,
+    #[serde(default)]
+    pub shockburst: bool,
+    #[serde(default = "default_crc_length")]
+    pub crc_length: usize,
+}
+
+fn default_address_length() -> usize {
+    5
+}
+
+fn default_crc_length() -> usize {
+    2
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    hex::decode(s).map_err(serde::de::Error::custom)
+}
+
+impl Target {
+    pub fn validate(&self) -> Result<()> {
+        let error = |msg: &str| {
+            let address = match self.address.is_empty() {
+                true => "*".to_string(),
+                false => hex::encode(&self.address),
+            };
+            Err(anyhow!("Target {}: {}", address, msg))
+        };
+        if !(3..=5).contains(&self.address_length) {
+            return error("address length must be 3, 4 or 5");
+        }
+        if self.address.len() > self.address_length {
+            return error("address exceeds address length");
+        }
+        if self.payload_length.is_some_and(|len| len > 32) {
+            return error("payload length exceeds 32 bytes");
+        }
+        if !self.shockburst && self.payload_length.is_none() {
+            return error("payload length must be given without Enhanced ShockBurst");
+        }
+        if self.crc_length > 2 {
+            return error("CRC length must be 0, 1 or 2");
+        }
+        if self.crc_length == 0 && self.address.is_empty() {
+            return error("address prefix is required without CRC");
+        }
+        Ok(())
+    }
+
+    // number of bits noise has to match by chance to pass as a frame, i.e. the alternating bits of
+    // the preamble up to the first address bit, the address prefix, the checksum and the payload
+    // length of ESB frames
+    pub fn matched_bits(&self) -> f64 {
+        let length = match (self.shockburst, self.payload_length) {
+            (true, Some(_)) => 6.0,
+            // payload lengths above 32 bytes are rejected
+            (true, None) => (64.0f64 / 33.0).log2(),
+            (false, _) => 0.0,
+        };
+        8.0 + 8.0 * (self.address.len() + self.crc_length) as f64 + length
+    }
+
+    pub fn config(&self, channel: u8) -> NrfConfig {
+        let config = if self.shockburst {
+            NrfConfig::shockburst(
+                channel,
+                self.address_length,
+                self.payload_length,
+                &self.address,
+            )
+        } else {
+            NrfConfig::fixed_length(
+                channel,
+                self.address_length,
+                self.payload_length.unwrap_or_default(),
+                &self.address,
+            )
+        };
+        config.crc_length(self.crc_length)
+    }
+}
+
+/// Loads targets from a JSON file holding a list of targets
+pub fn load(path: &str) -> Result<Vec<Target>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+fn parse(json: &str) -> Result<Vec<Target>> {
+    let targets: Vec<Target> = serde_json::from_str(json)?;
+    for target in &targets {
+        target.validate()?;
+    }
+    Ok(targets)
+}
+
+#[test]
+fn test_parse() {
+    let targets = parse(
+        r#"[
+            {"address": "e7e7", "shockburst": true},
+            {"address": "0707", "address_length": 3, "payload_length": 25, "crc_length": 1}
+        ]"#,
+    )
+    .expect("Parsing failed");
+    assert_eq!(
+        targets,
+        vec![
+            Target {
+                address: vec![0xe7, 0xe7],
+                address_length: 5,
+                payload_length: None,
+                shockburst: true,
+                crc_length: 2,
+            },
+            Target {
+                address: vec![0x07, 0x07],
+                address_length: 3,
+                payload_length: Some(25),
+                shockburst: false,
+                crc_length: 1,
+            },
+        ]
+    );
+    assert!(parse(r#"[{"address": "e7e7"}]"#).is_err());
+    assert!(parse(r#"[{"address": "", "payload_length": 4, "crc_length": 0}]"#).is_err());
+    assert!(
+        parse(r#"[{"address": "0102030405", "address_length": 4, "shockburst": true}]"#).is_err()
+    );
+}
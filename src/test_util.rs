@@ -0,0 +1,16 @@
+use rustradio::stream::Streamp;
+
+/// Stream holding the given items, as if written by a preceding block
+pub fn stream_of<T: Copy>(items: &[T]) -> Streamp<T> {
+    let stream = rustradio::stream::Stream::newp();
+    let mut buf = stream.write_buf().expect("Stream not writable");
+    buf.fill_from_slice(items);
+    buf.produce(items.len(), &[]);
+    stream
+}
+
+/// Streams holding the given items and their positions, counting from zero
+pub fn stream_with_positions<T: Copy>(items: &[T]) -> (Streamp<T>, Streamp<u64>) {
+    let positions: Vec<u64> = (0..items.len() as u64).collect();
+    (stream_of(items), stream_of(&positions))
+}
@@ -1,3 +1,4 @@
+use crate::eof;
 use rustradio::block::{Block, BlockEOF, BlockName, BlockRet};
 use rustradio::stream::{NoCopyStream, NoCopyStreamp};
 use rustradio::Error;
@@ -24,9 +25,11 @@ impl<T> Union<T> {
 
 impl<T> Block for Union<T> {
     fn work(&mut self) -> Result<BlockRet, Error> {
-        if let Some((val, tags)) = self.a.pop().or(self.b.pop()) {
+        if let Some((val, tags)) = self.a.pop().or_else(|| self.b.pop()) {
             self.dst.push(val, &tags);
             Ok(BlockRet::Ok)
+        } else if self.eof() {
+            Ok(BlockRet::EOF)
         } else {
             Ok(BlockRet::Noop)
         }
@@ -34,6 +37,9 @@ impl<T> Block for Union<T> {
 }
 
 impl<T> BlockEOF for Union<T> {
+    fn eof(&mut self) -> bool {
+        eof::propagate(&(self.a.clone(), self.b.clone()), &self.dst)
+    }
 }
 
 impl<T> BlockName for Union<T> {
@@ -0,0 +1,95 @@
+-- Wireshark dissector for packets written by nrf-probe using --pcap
+--
+-- Installation: copy into the personal Lua plugins folder of Wireshark,
+-- e.g. ~/.local/lib/wireshark/plugins/ on Linux.
+
+local nrf = Proto("nrf", "Nordic Semiconductor nRF")
+
+local flags = {
+    crc_ok = 0x01,
+    pcf = 0x02,
+    no_ack = 0x04,
+    ble = 0x08,
+    no_crc = 0x10,
+}
+
+local ble_pdu_types = {
+    [0] = "ADV_IND",
+    [1] = "ADV_DIRECT_IND",
+    [2] = "ADV_NONCONN_IND",
+    [3] = "SCAN_REQ",
+    [4] = "SCAN_RSP",
+    [5] = "CONNECT_IND",
+    [6] = "ADV_SCAN_IND",
+    [7] = "ADV_EXT_IND",
+}
+
+local f = nrf.fields
+f.version = ProtoField.uint8("nrf.version", "Header Version")
+f.channel = ProtoField.uint8("nrf.channel", "Channel")
+f.rate = ProtoField.uint16("nrf.rate", "Data Rate (kbps)")
+f.address_length = ProtoField.uint8("nrf.address_length", "Address Length")
+f.flags = ProtoField.uint8("nrf.flags", "Flags", base.HEX)
+f.crc_ok = ProtoField.bool("nrf.flags.crc_ok", "CRC Valid", 8, nil, flags.crc_ok)
+f.pcf_present = ProtoField.bool("nrf.flags.pcf", "ESB PCF Present", 8, nil, flags.pcf)
+f.no_ack = ProtoField.bool("nrf.flags.no_ack", "NO_ACK", 8, nil, flags.no_ack)
+f.ble = ProtoField.bool("nrf.flags.ble", "BLE Advertising PDU", 8, nil, flags.ble)
+f.no_crc = ProtoField.bool("nrf.flags.no_crc", "CRC Disabled", 8, nil, flags.no_crc)
+f.payload_length = ProtoField.uint8("nrf.pcf.length", "Payload Length")
+f.pid = ProtoField.uint8("nrf.pcf.pid", "Packet ID")
+f.pdu_type = ProtoField.uint8("nrf.ble.pdu_type", "PDU Type", base.DEC, ble_pdu_types, 0x0f)
+f.tx_add = ProtoField.bool("nrf.ble.tx_add", "TxAdd (random)", 8, nil, 0x40)
+f.rx_add = ProtoField.bool("nrf.ble.rx_add", "RxAdd (random)", 8, nil, 0x80)
+f.pdu_length = ProtoField.uint8("nrf.ble.length", "PDU Length")
+f.address = ProtoField.bytes("nrf.address", "Address")
+f.payload = ProtoField.bytes("nrf.payload", "Payload")
+
+function nrf.dissector(buffer, pinfo, tree)
+    if buffer:len() < 8 then
+        return 0
+    end
+    pinfo.cols.protocol = "nRF"
+
+    local address_length = buffer(4, 1):uint()
+    local flag_bits = buffer(5, 1):uint()
+    local subtree = tree:add(nrf, buffer(), "Nordic Semiconductor nRF")
+
+    subtree:add(f.version, buffer(0, 1))
+    subtree:add(f.channel, buffer(1, 1))
+    subtree:add(f.rate, buffer(2, 2))
+    subtree:add(f.address_length, buffer(4, 1))
+    local flag_tree = subtree:add(f.flags, buffer(5, 1))
+    flag_tree:add(f.crc_ok, buffer(5, 1))
+    flag_tree:add(f.pcf_present, buffer(5, 1))
+    flag_tree:add(f.no_ack, buffer(5, 1))
+    flag_tree:add(f.ble, buffer(5, 1))
+    flag_tree:add(f.no_crc, buffer(5, 1))
+    if bit.band(flag_bits, flags.pcf) ~= 0 then
+        subtree:add(f.payload_length, buffer(6, 1))
+        subtree:add(f.pid, buffer(7, 1))
+    elseif bit.band(flag_bits, flags.ble) ~= 0 then
+        subtree:add(f.pdu_type, buffer(6, 1))
+        subtree:add(f.tx_add, buffer(6, 1))
+        subtree:add(f.rx_add, buffer(6, 1))
+        subtree:add(f.pdu_length, buffer(7, 1))
+    end
+
+    local address = buffer(8, address_length)
+    subtree:add(f.address, address)
+    local info = "Ch " .. buffer(1, 1):uint() .. " Addr " .. address:bytes():tohex():lower()
+    if buffer:len() > 8 + address_length then
+        subtree:add(f.payload, buffer(8 + address_length))
+    end
+    if bit.band(flag_bits, flags.ble) ~= 0 then
+        info = info .. " " .. (ble_pdu_types[bit.band(buffer(6, 1):uint(), 0x0f)] or "RESERVED")
+    end
+    if bit.band(flag_bits, flags.no_crc) ~= 0 then
+        info = info .. " [no CRC]"
+    elseif bit.band(flag_bits, flags.crc_ok) == 0 then
+        info = info .. " [CRC error]"
+    end
+    pinfo.cols.info = info
+    return buffer:len()
+end
+
+DissectorTable.get("wtap_encap"):add(wtap.USER0, nrf)